@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+use dota::components::items::Items;
+use serde::{Deserialize, Serialize};
+use crate::metadata;
+use super::GameData;
+
+/// How many of the latest events the live embed lists.
+pub const SHOWN_EVENTS: usize = 5;
+
+/// Dota's window for chaining kills into a multi-kill, in seconds.
+const MULTI_KILL_WINDOW: i32 = 18;
+
+const LEVELS: &[u32] = &[6, 12, 18, 25];
+
+/// Items worth announcing when they show up in a player's inventory.
+const BIG_ITEMS: &[&str] = &[
+	"item_blink", "item_black_king_bar", "item_ultimate_scepter", "item_refresher", "item_sheepstick",
+	"item_rapier", "item_radiance", "item_butterfly", "item_satanic", "item_skadi", "item_heart", "item_assault",
+	"item_monkey_king_bar", "item_abyssal_blade", "item_bloodthorn", "item_nullifier", "item_octarine_core",
+	"item_aeon_disk", "item_shivas_guard", "item_greater_crit", "item_desolator", "item_manta", "item_travel_boots",
+	"item_travel_boots_2", "item_overwhelming_blink", "item_swift_blink", "item_arcane_blink", "item_harpoon",
+	"item_wind_waker", "item_gungir", "item_disperser", "item_revenants_brooch",
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EventKind {
+	FirstBlood,
+	/// Killing sprees and multi-kills.
+	KillStreak,
+	Death,
+	Level,
+	Item,
+	Buyback,
+	Aegis,
+}
+
+impl EventKind {
+	/// Choice values for the `/events` command, with their labels.
+	pub const ALL: &'static [(&'static str, &'static str, EventKind)] = &[
+		("first_blood", "First blood", EventKind::FirstBlood),
+		("kill_streak", "Kill streaks", EventKind::KillStreak),
+		("death", "Deaths", EventKind::Death),
+		("level", "Levels 6/12/18/25", EventKind::Level),
+		("item", "Big items", EventKind::Item),
+		("buyback", "Buybacks", EventKind::Buyback),
+		("aegis", "Aegis", EventKind::Aegis),
+	];
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		return EventKind::ALL.iter().find(|(n, _, _)| *n == name).map(|(_, _, k)| *k);
+	}
+
+	fn bit(self) -> u32 {
+		return 1 << self as u32;
+	}
+}
+
+/// Which events a guild's channel announces. Stored as the set of muted events, so new kinds start out enabled.
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct EventFilter {
+	muted: u32,
+}
+
+impl EventFilter {
+	pub fn from_muted(muted: u32) -> Self {
+		return EventFilter { muted };
+	}
+
+	pub fn muted(self) -> u32 {
+		return self.muted;
+	}
+
+	pub fn allows(self, kind: EventKind) -> bool {
+		return self.muted & kind.bit() == 0;
+	}
+
+	pub fn set(&mut self, kind: EventKind, enabled: bool) {
+		if enabled {
+			self.muted &= !kind.bit();
+		} else {
+			self.muted |= kind.bit();
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct GameEvent {
+	pub kind: EventKind,
+	/// Game clock when the event was noticed.
+	pub clock: i32,
+	pub text: String,
+}
+
+/// What has to be remembered across updates, beyond the previous state, to spot multi-kills.
+#[derive(Default)]
+pub struct EventTracker {
+	multi_kill: u32,
+	last_kill: i32,
+}
+
+impl EventTracker {
+	/// Compares two consecutive states of the same player in the same match.
+	pub(super) fn detect(&mut self, old: &GameData, new: &GameData) -> Vec<GameEvent> {
+		let mut events = Vec::new();
+		let clock = new.map.clock_time;
+		let mut push = |kind, text: String| events.push(GameEvent { kind, clock, text });
+
+		let (old_player, new_player) = (&old.player_info, &new.player_info);
+		let (old_hero, new_hero) = (&old.hero, &new.hero);
+
+		let kills = new_player.kills.saturating_sub(old_player.kills);
+		let died = new_player.deaths > old_player.deaths;
+		let first_blood = old.map.radiant_score + old.map.dire_score == 0 && new.map.radiant_score + new.map.dire_score > 0;
+
+		if first_blood && kills > 0 {
+			push(EventKind::FirstBlood, "Drew first blood!".to_owned());
+		} else if first_blood && died {
+			push(EventKind::FirstBlood, "Was first blood.".to_owned());
+		} else if died {
+			push(EventKind::Death, format!("Died ({} deaths)", new_player.deaths));
+		}
+
+		if kills > 0 {
+			let before = self.multi_kill;
+			for _ in 0..kills {
+				if self.multi_kill > 0 && clock - self.last_kill <= MULTI_KILL_WINDOW {
+					self.multi_kill += 1;
+				} else {
+					self.multi_kill = 1;
+				}
+				self.last_kill = clock;
+			}
+
+			if self.multi_kill >= 3 && self.multi_kill > before {
+				push(EventKind::KillStreak, format!("{}!", multi_kill_name(self.multi_kill)));
+			}
+		}
+
+		if new_player.kill_streak > old_player.kill_streak {
+			if let Some(name) = kill_streak_name(new_player.kill_streak) {
+				push(EventKind::KillStreak, format!("{} ({} kills without dying)", name, new_player.kill_streak));
+			}
+		}
+
+		if let (Some(old_level), Some(new_level)) = (old_hero.level, new_hero.level) {
+			for level in LEVELS.iter().filter(|l| old_level < **l && **l <= new_level) {
+				push(EventKind::Level, format!("Reached level {}", level));
+			}
+		}
+
+		if let (Some(old_cooldown), Some(new_cooldown)) = (old_hero.buyback_cooldown, new_hero.buyback_cooldown) {
+			if new_cooldown > old_cooldown {
+				push(EventKind::Buyback, "Bought back!".to_owned());
+			}
+		}
+
+		// Without an earlier inventory to compare against, everything would look new.
+		if let (Some(old_items), Some(new_items)) = (&old.items, &new.items) {
+			let old_items = item_names(old_items);
+
+			for item in item_names(new_items).difference(&old_items) {
+				if *item == "item_aegis" {
+					push(EventKind::Aegis, "Picked up the Aegis!".to_owned());
+				} else if BIG_ITEMS.contains(item) {
+					push(EventKind::Item, format!("Got {}", metadata::item_name(item)));
+				}
+			}
+		}
+
+		return events;
+	}
+}
+
+/// Everything the player carries, leaving out empty slots.
+fn item_names(items: &Items) -> HashSet<&str> {
+	return [
+		&items.slot0, &items.slot1, &items.slot2, &items.slot3, &items.slot4, &items.slot5,
+		&items.slot6, &items.slot7, &items.slot8,
+		&items.stash0, &items.stash1, &items.stash2, &items.stash3, &items.stash4, &items.stash5,
+	].into_iter()
+		.map(|item| item.name.as_str())
+		.filter(|name| *name != "empty")
+		.collect();
+}
+
+fn multi_kill_name(kills: u32) -> &'static str {
+	return match kills {
+		3 => "Triple Kill",
+		4 => "Ultra Kill",
+		_ => "Rampage",
+	};
+}
+
+fn kill_streak_name(streak: u32) -> Option<&'static str> {
+	return match streak {
+		3 => Some("Killing Spree"),
+		4 => Some("Dominating"),
+		5 => Some("Mega Kill"),
+		6 => Some("Unstoppable"),
+		7 => Some("Wicked Sick"),
+		8 => Some("Monster Kill"),
+		9 => Some("Godlike"),
+		10 => Some("Beyond Godlike"),
+		_ => None,
+	};
+}
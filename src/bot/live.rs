@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use serenity::builder::CreateEmbed;
+use serenity::model::id::{ChannelId, MessageId};
+use serenity::utils::Colour;
+use super::events::{EventFilter, EventTracker, GameEvent, SHOWN_EVENTS};
+use crate::metadata::hero_name;
+use super::{build_message, build_summary, death_status, format_clock, map_fields, match_result, shown_events, GameData, SteamId};
+
+/// How long a player in a party can go without updates before their entry says so.
+const LAGGING_AFTER: Duration = Duration::from_secs(30);
+
+/// One message showing a match in one channel.
+pub struct Post {
+	pub channel: ChannelId,
+	pub message: MessageId,
+	/// The events the channel's guild wants announced.
+	pub events: EventFilter,
+	/// The tracked players in the match that are tracked in this channel's guild.
+	pub players: Vec<SteamId>,
+}
+
+pub struct PlayerGame {
+	/// The latest state, which the summary is built from once the player is done.
+	pub data: GameData,
+	pub last_update: Instant,
+	pub events: Vec<GameEvent>,
+	pub tracker: EventTracker,
+	/// Set once the player's result has been recorded. Their last state stays up until the whole match is over.
+	pub ended: bool,
+}
+
+impl PlayerGame {
+	pub fn new(data: GameData) -> Self {
+		return PlayerGame {
+			data,
+			last_update: Instant::now(),
+			events: Vec::new(),
+			tracker: EventTracker::default(),
+			ended: false,
+		};
+	}
+}
+
+/// A match with at least one tracked player in it, and everywhere it is posted.
+#[derive(Default)]
+pub struct LiveMatch {
+	pub players: HashMap<SteamId, PlayerGame>,
+	pub posts: Vec<Post>,
+}
+
+impl LiveMatch {
+	pub fn is_over(&self) -> bool {
+		return self.players.values().all(|player| player.ended);
+	}
+
+	/// A post shows the usual embed while it has one player, and the party embed once it has more.
+	pub fn render(&self, post: &Post) -> CreateEmbed {
+		let mut e = CreateEmbed::default();
+
+		let players = post.players.iter()
+			.filter_map(|steam_id| self.players.get(steam_id))
+			.collect::<Vec<_>>();
+
+		match players.as_slice() {
+			[] => {}
+			[player] if player.ended => {
+				build_summary(&mut e, &player.data, match_result(&player.data));
+			}
+			[player] => {
+				build_message(&mut e, &player.data, &shown_events(&player.events, post.events));
+			}
+			players => {
+				build_party(&mut e, players, post.events);
+			}
+		}
+
+		return e;
+	}
+}
+
+fn build_party<'a>(e: &'a mut CreateEmbed, players: &[&PlayerGame], filter: EventFilter) -> &'a mut CreateEmbed {
+	// Whoever reported last has the freshest clock and score.
+	let latest = players.iter().max_by_key(|player| player.last_update).unwrap();
+	let map = &latest.data.map;
+
+	let results = players.iter()
+		.map(|player| match_result(&player.data))
+		.collect::<Vec<_>>();
+
+	if players.iter().all(|player| player.ended) {
+		let (header, colour) = if results.iter().all(|won| *won == Some(true)) {
+			("VICTORY", Colour::DARK_GREEN)
+		} else if results.iter().all(|won| *won == Some(false)) {
+			("DEFEAT", Colour::RED)
+		} else {
+			("MATCH ENDED", Colour::LIGHT_GREY)
+		};
+		e.title(format!("{} - a party of {}", header, players.len()));
+		e.colour(colour);
+	} else {
+		e.title(format!("A party of {} is playing a match!", players.len()));
+	}
+
+	map_fields(e, map);
+
+	for (player, won) in players.iter().zip(&results) {
+		let info = &player.data.player_info;
+		let hero = &player.data.hero;
+
+		let mut value = format!(
+			"{}Lvl {} | K/D/A {}/{}/{} | CS/DN {}/{} | XPM/GPM {}/{}",
+			hero.name.as_deref().map(|name| format!("{} | ", hero_name(name))).unwrap_or_default(),
+			hero.level.unwrap_or_default(),
+			info.kills, info.deaths, info.assists, info.last_hits, info.denies, info.xpm, info.gpm,
+		);
+
+		if let Some(status) = death_status(hero, info.gold).filter(|_| !player.ended) {
+			value.push_str(&format!("\n{}", status));
+		}
+
+		if player.ended {
+			value.push_str(match won {
+				Some(true) => "\nVictory",
+				Some(false) => "\nDefeat",
+				None => "\nStopped reporting",
+			});
+		} else if player.last_update.elapsed() > LAGGING_AFTER {
+			value.push_str(&format!("\nNo updates for {}s", player.last_update.elapsed().as_secs()));
+		}
+
+		e.field(format!("{} ({})", info.name, info.team_name), value, false);
+	}
+
+	let mut events = players.iter()
+		.flat_map(|player| {
+			let name = &player.data.player_info.name;
+			player.events.iter()
+				.filter(|event| filter.allows(event.kind))
+				.map(move |event| (event.clock, format!("**{}** {}", name, event.text)))
+		})
+		.collect::<Vec<_>>();
+	events.sort_by_key(|(clock, _)| *clock);
+
+	if !events.is_empty() {
+		let lines = events[events.len().saturating_sub(SHOWN_EVENTS)..].iter()
+			.map(|(clock, text)| format!("`{}` {}", format_clock(*clock), text))
+			.collect::<Vec<_>>()
+			.join("\n");
+		e.field("Events", lines, false);
+	}
+
+	e.footer(|f| {
+		f.text(format!("Match ID: {}", latest.data.match_id))
+	});
+
+	e.timestamp(Utc::now());
+
+	return e;
+}
@@ -1,32 +1,66 @@
 use std::collections::{HashMap, HashSet};
-use std::fs::File;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use chrono::Utc;
-use dota::components::{DotaGameRulesState, GameState, Map};
+use dota::components::{DotaGameRulesState, GameState, Map, Team};
+use dota::components::abilities::{Ability, GameAbilities};
 use dota::components::heroes::{GameHeroes, Hero};
+use dota::components::items::{GameItems, Item, Items};
 use dota::components::players::{GamePlayers, PlayerInformation};
-use rmp_serde::{decode, encode};
 use rusty_ulid::Ulid;
 use serenity::builder::CreateEmbed;
 use serenity::CacheAndHttp;
-use serenity::model::channel::Message;
-use serenity::model::id::{ChannelId, UserId};
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::utils::Colour;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
-use serde::{Serialize, Deserialize};
+use tokio::sync::watch;
+
+use crate::metadata::{self, hero_name};
+use crate::storage::{MatchRecord, Storage, StorageError, UserInfo};
+
+pub mod events;
+mod live;
+pub mod spectate;
+pub mod stats;
+mod updates;
+
+use self::events::{EventFilter, EventKind, EventTracker, GameEvent, SHOWN_EVENTS};
+use self::live::{LiveMatch, PlayerGame, Post};
+use self::spectate::{Scoreboard, SpectateMode, Spectated};
+use self::stats::{Metric, Stats};
+use self::updates::{EditResult, Updater};
+
+pub use self::updates::DEFAULT_EDIT_INTERVAL;
 
 pub type SteamId = u64;
 
-struct GamePosts {
-	match_id: u64,
-	messages: Vec<Message>,
-}
+/// A hero's abilities, keyed by their slot in GSI, e.g. `ability0`.
+pub type Abilities = HashMap<String, Ability>;
+
+/// How long a player can go without updates before their client is assumed to have disconnected.
+const STALE_TIMEOUT: Duration = Duration::from_secs(120);
 
-#[derive(Serialize, Deserialize, Debug, Eq, Hash, PartialEq, Copy, Clone)]
-struct UserInfo {
-	token: Ulid,
-	steam_id: SteamId,
+/// How long a player's own client keeps priority over spectators after its last update.
+const OWN_CLIENT_PRIORITY: Duration = Duration::from_secs(10);
+
+/// How often to look for such matches.
+const STALE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How many users `/leaderboard` ranks.
+const LEADERBOARD_SIZE: usize = 10;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BotError {
+	/// The user has no registration to act on.
+	NotRegistered,
+	/// The guild has no bound channel.
+	NotBound,
+	/// Another user already registered the Steam account.
+	SteamIdTaken,
+	/// The storage backend failed, so the change may not have been applied.
+	Storage,
 }
 
 #[derive(Debug)]
@@ -34,102 +68,153 @@ pub enum BotRequest {
 	RegisterUser {
 		user: UserId,
 		steam_id: SteamId,
-		resp: oneshot::Sender<Result<Ulid, ()>>
+		resp: oneshot::Sender<Result<Ulid, BotError>>
+	},
+	/// Revokes every token the user holds and issues a single new one for all of their Steam accounts.
+	RotateToken {
+		user: UserId,
+		resp: oneshot::Sender<Result<Ulid, BotError>>
+	},
+	UnregisterUser {
+		user: UserId,
+		resp: oneshot::Sender<Result<(), BotError>>
 	},
+	/// Sets the guild's output channel. Tracks follow the guild, so they move along with it.
 	BindChannel {
+		guild: GuildId,
 		channel: ChannelId,
-		resp: oneshot::Sender<Result<(), ()>>
+		resp: oneshot::Sender<Result<(), BotError>>
 	},
+	/// Fails if the guild has no bound channel, otherwise responds with the channel matches will be posted in.
 	AddTrack {
 		user: UserId,
-		channel: ChannelId,
-		resp: oneshot::Sender<Result<(), ()>>
+		guild: GuildId,
+		resp: oneshot::Sender<Result<ChannelId, BotError>>
 	},
 	RemoveTrack {
 		user: UserId,
-		channel: ChannelId,
-		resp: oneshot::Sender<Result<(), ()>>
+		guild: GuildId,
+		resp: oneshot::Sender<Result<(), BotError>>
+	},
+	/// Responds with up to `limit` of the user's recorded matches, most recent first, after skipping `offset`.
+	MatchHistory {
+		user: UserId,
+		offset: usize,
+		limit: usize,
+		resp: oneshot::Sender<Result<Vec<MatchRecord>, BotError>>
+	},
+	/// Aggregates the user's matches recorded since `since` (all of them if `None`), optionally only those on `hero`.
+	MatchStats {
+		user: UserId,
+		hero: Option<String>,
+		since: Option<i64>,
+		resp: oneshot::Sender<Result<Stats, BotError>>
+	},
+	/// Ranks the users tracked in the guild by `metric` over matches recorded since `since`, best first.
+	/// Users that don't qualify for the metric are left out. Fails if the guild has no bound channel.
+	Leaderboard {
+		guild: GuildId,
+		metric: Metric,
+		since: Option<i64>,
+		resp: oneshot::Sender<Result<Vec<(UserId, Stats)>, BotError>>
+	},
+	/// Turns announcing `kind` (every kind if `None`) on or off for the guild, responding with the resulting filter.
+	SetEvents {
+		guild: GuildId,
+		kind: Option<EventKind>,
+		enabled: bool,
+		resp: oneshot::Sender<Result<EventFilter, BotError>>
+	},
+	/// Sets what the user's client does with matches it spectates.
+	SetSpectate {
+		user: UserId,
+		mode: SpectateMode,
+		resp: oneshot::Sender<Result<(), BotError>>
 	},
-}
-
-#[derive(Serialize, Deserialize)]
-struct SaveData {
-	channels: HashSet<ChannelId>,
-	users: HashMap<UserInfo, UserId>,
-	tracks: HashMap<UserId, HashSet<ChannelId>>,
-}
-
-impl SaveData {
-	fn new() -> Self {
-		Self {
-			channels: HashSet::new(),
-			users: HashMap::new(),
-			tracks: HashMap::new(),
-		}
-	}
 }
 
 struct GameData {
-	map: Map,
+	/// Shared between the players of a spectated match.
+	map: Arc<Map>,
 	player_info: PlayerInformation,
 	hero: Hero,
+	/// Only there if the client's cfg asks for the items block.
+	items: Option<Items>,
+	/// Only there if the client's cfg asks for the abilities block.
+	abilities: Option<Abilities>,
 	match_id: u64,
+	/// The token is whichever one the reporting client used, which is the spectator's for spectated data.
 	user_info: UserInfo,
 	user_id: UserId,
+	/// Whether this came from someone spectating rather than the player's own client.
+	spectated: bool,
+}
+
+impl GameData {
+	/// Whether both states came from the same client, i.e. the player's own or one particular spectator's.
+	fn same_source(&self, other: &GameData) -> bool {
+		return self.spectated == other.spectated && self.user_info.token == other.user_info.token;
+	}
 }
 
 pub struct Bot {
 	bot_req_rx: mpsc::Receiver<BotRequest>,
 	gsi_rx: mpsc::Receiver<GameState>,
+	tokens_tx: watch::Sender<HashSet<Ulid>>,
 	cah: Arc<CacheAndHttp>,
-	games: HashMap<SteamId, GamePosts>,
-	save: SaveData,
+	/// Matches with tracked players in them, by match ID.
+	matches: HashMap<u64, LiveMatch>,
+	/// The match each account is currently reporting from.
+	playing: HashMap<SteamId, u64>,
+	/// Spectators' scoreboards, by match ID.
+	scoreboards: HashMap<u64, Scoreboard>,
+	updater: Updater,
+	edit_rx: mpsc::Receiver<EditResult>,
+	/// The last match each account finished, so the updates GSI keeps sending after the game ends are ignored.
+	finished: HashMap<SteamId, u64>,
+	/// The last match each account was only seen finishing by a spectator. Their own client can still record it.
+	spectated_finished: HashMap<SteamId, u64>,
+	storage: Box<dyn Storage>,
 }
 
 impl Bot {
-	pub fn new(cah: Arc<CacheAndHttp>, bot_req_rx: mpsc::Receiver<BotRequest>, gsi_rx: mpsc::Receiver<GameState>) -> Self {
-		let data_file = File::open("stalker.dat");
-		let save = if data_file.is_ok() {
-			let res = decode::from_read(data_file.unwrap());
-			if res.is_ok() {
-				res.unwrap()
-			} else {
-				log::error!("Error decoding data from stalker.dat! Using an empty dataset!");
-				SaveData::new()
-			}
-		} else {
-			log::warn!("Could not open stalker.dat! (First run?)");
-			SaveData::new()
-		};
-
-		for channel in &save.channels {
-			log::debug!("Loaded binding to channel {}", channel);
-		}
+	/// `edit_interval` is the least time between two edits of the same post.
+	pub fn new(cah: Arc<CacheAndHttp>, storage: Box<dyn Storage>, bot_req_rx: mpsc::Receiver<BotRequest>, gsi_rx: mpsc::Receiver<GameState>, tokens_tx: watch::Sender<HashSet<Ulid>>, edit_interval: Duration) -> Result<Self, StorageError> {
+		let (edit_tx, edit_rx) = mpsc::channel(10);
 
-		for user in &save.users {
-			log::debug!("Loaded user {:#?}", user);
-		}
-
-		for track in &save.tracks {
-			log::debug!("Loaded tracks for user {}: {:#?}", track.0, track.1);
-		}
-
-		return Bot {
+		let bot = Bot {
 			bot_req_rx,
 			gsi_rx,
+			tokens_tx,
+			updater: Updater::new(cah.http.clone(), edit_interval, edit_tx),
+			edit_rx,
 			cah,
-			games: HashMap::new(),
-			save,
-		}
+			matches: HashMap::new(),
+			playing: HashMap::new(),
+			scoreboards: HashMap::new(),
+			finished: HashMap::new(),
+			spectated_finished: HashMap::new(),
+			storage,
+		};
+
+		let _ = bot.tokens_tx.send(bot.storage.tokens()?);
+
+		return Ok(bot);
 	}
 
 	pub async fn run(mut self) {
 		log::info!("Starting bot handler!");
 
+		let mut stale_check = tokio::time::interval(STALE_CHECK_INTERVAL);
+		let mut flush = tokio::time::interval(updates::FLUSH_INTERVAL);
+
 		loop {
 			tokio::select! {
 	            Some(data) = self.bot_req_rx.recv() => self.handle_bot_request(data).await,
 	            Some(data) = self.gsi_rx.recv() => self.handle_game_state(data).await,
+	            Some(result) = self.edit_rx.recv() => self.handle_edit_result(result),
+	            _ = flush.tick() => self.updater.flush(),
+	            _ = stale_check.tick() => self.finish_stale_matches(),
 	            else => { break }
 	        };
 		}
@@ -137,75 +222,208 @@ impl Bot {
 		log::warn!("Bot handler killed!");
 	}
 
-	pub fn write_data(&mut self) {
-		let data_file = File::create("stalker.dat");
-		encode::write(&mut data_file.unwrap(), &self.save).unwrap();
+	/// Forgets posts that were deleted out from under us.
+	fn handle_edit_result(&mut self, result: EditResult) {
+		if let Some(gone) = self.updater.handle_result(result) {
+			for live in self.matches.values_mut() {
+				live.posts.retain(|post| post.message != gone);
+			}
+			for scoreboard in self.scoreboards.values_mut() {
+				scoreboard.posts.retain(|(_, message)| *message != gone);
+			}
+		}
+	}
+
+	/// Hands the current set of valid auth tokens to the GSI server.
+	fn publish_tokens(&self) {
+		match self.storage.tokens() {
+			Ok(tokens) => {
+				let _ = self.tokens_tx.send(tokens);
+			}
+			Err(e) => log::error!("Failed to read auth tokens! `{}`", e),
+		}
+	}
+
+	/// The user's registered Steam accounts, or `NotRegistered` if there are none.
+	fn registered_steam_ids(&self, user: UserId) -> Result<HashSet<SteamId>, BotError> {
+		match self.storage.user_steam_ids(user).map_err(storage_error)? {
+			ids if ids.is_empty() => Err(BotError::NotRegistered),
+			ids => Ok(ids),
+		}
 	}
 
 	pub async fn handle_bot_request(&mut self, data: BotRequest) {
 		match data {
-			BotRequest::BindChannel { channel, resp } => {
-				self.save.channels.insert(channel);
-				self.write_data();
-				resp.send(Ok(())).unwrap();
-			}
-			BotRequest::AddTrack { user, channel, resp } => {
-				if self.save.channels.contains(&channel) {
-					match self.save.tracks.get_mut(&user) {
-						None => {
-							let mut set = HashSet::new();
-							set.insert(channel);
-							self.save.tracks.insert(user, set);
-						}
-						Some(tracks) => {
-							tracks.insert(channel);
-							self.write_data();
-						}
+			BotRequest::BindChannel { guild, channel, resp } => {
+				let result = self.storage.bind_guild(guild, channel).map_err(storage_error).map(|old| {
+					if let Some(old) = old {
+						log::info!("Moving guild {} from channel {} to {}", guild, old, channel);
 					}
-					resp.send(Ok(())).unwrap();
-				} else {
-					resp.send(Err(())).unwrap();
-				}
+				});
+				let _ = resp.send(result);
 			}
-			BotRequest::RemoveTrack { user, channel, resp } => {
-				match self.save.tracks.get_mut(&user) {
-					None => {}
-					Some(tracks) => {
-						let removed = tracks.remove(&channel);
-						if removed {
-							if tracks.is_empty() {
-								self.save.tracks.remove(&user);
-							}
-							self.write_data();
-						}
-					}
-				}
-				resp.send(Ok(())).unwrap();
+			BotRequest::AddTrack { user, guild, resp } => {
+				let result = match self.storage.guild_channel(guild).map_err(storage_error) {
+					Ok(Some(channel)) => self.storage.add_track(user, guild).map_err(storage_error).map(|_| channel),
+					Ok(None) => Err(BotError::NotBound),
+					Err(e) => Err(e),
+				};
+				let _ = resp.send(result);
+			}
+			BotRequest::RemoveTrack { user, guild, resp } => {
+				let _ = resp.send(self.storage.remove_track(user, guild).map_err(storage_error));
 			}
 			BotRequest::RegisterUser { user, steam_id, resp } => {
+				// Matches of a Steam account are attributed to a single user, so it can't be claimed twice.
+				match self.storage.steam_user(steam_id) {
+					Ok(Some(owner)) if owner != user => {
+						log::info!("User {} tried to register SteamID {}, which belongs to {}", user, steam_id, owner);
+						let _ = resp.send(Err(BotError::SteamIdTaken));
+						return;
+					}
+					Ok(_) => {}
+					Err(e) => {
+						let _ = resp.send(Err(storage_error(e)));
+						return;
+					}
+				}
+
 				let user_info = UserInfo {
 					token: Ulid::generate(),
 					steam_id
 				};
 
-				self.save.users.insert(user_info, user);
-				self.write_data();
-				resp.send(Ok(user_info.token)).unwrap();
+				// Re-registering the same account replaces its old token rather than leaving it valid.
+				let result = self.storage.register_user(user, user_info).map_err(storage_error);
+				self.publish_tokens();
+				let _ = resp.send(result.map(|_| user_info.token));
+			}
+			BotRequest::RotateToken { user, resp } => {
+				if let Err(e) = self.registered_steam_ids(user) {
+					let _ = resp.send(Err(e));
+					return;
+				}
+
+				let token = Ulid::generate();
+				let result = self.storage.rotate_token(user, token).map_err(storage_error);
+
+				log::info!("Rotated auth token for user {}", user);
+
+				self.publish_tokens();
+				let _ = resp.send(result.map(|_| token));
+			}
+			BotRequest::UnregisterUser { user, resp } => {
+				let steam_ids = match self.registered_steam_ids(user) {
+					Ok(ids) => ids,
+					Err(e) => {
+						let _ = resp.send(Err(e));
+						return;
+					}
+				};
+
+				let result = self.storage.remove_user(user).map_err(storage_error);
+				for steam_id in &steam_ids {
+					self.drop_player(*steam_id);
+				}
+
+				let updater = &mut self.updater;
+				self.scoreboards.retain(|_, scoreboard| {
+					if scoreboard.spectator != user {
+						return true;
+					}
+					for (_, message) in &scoreboard.posts {
+						updater.remove(*message);
+					}
+					return false;
+				});
+
+				log::info!("Unregistered user {}", user);
+
+				self.publish_tokens();
+				let _ = resp.send(result);
+			}
+			BotRequest::MatchHistory { user, offset, limit, resp } => {
+				let _ = resp.send(self.storage.user_matches(user, offset, limit).map_err(storage_error));
+			}
+			BotRequest::MatchStats { user, hero, since, resp } => {
+				let result = self.storage.user_matches_since(user, since.unwrap_or(i64::MIN)).map_err(storage_error).map(|mut matches| {
+					if let Some(hero) = &hero {
+						matches.retain(|m| metadata::hero_matches(&m.hero, hero));
+					}
+					Stats::from_matches(&matches)
+				});
+				let _ = resp.send(result);
+			}
+			BotRequest::SetEvents { guild, kind, enabled, resp } => {
+				let _ = resp.send(self.set_events(guild, kind, enabled));
+			}
+			BotRequest::SetSpectate { user, mode, resp } => {
+				let result = self.registered_steam_ids(user)
+					.and_then(|_| self.storage.set_spectate_mode(user, mode).map_err(storage_error));
+
+				if result.is_ok() {
+					log::info!("Set spectate mode of {} to {:?}", user, mode);
+				}
+
+				let _ = resp.send(result);
+			}
+			BotRequest::Leaderboard { guild, metric, since, resp } => {
+				let result = match self.storage.guild_channel(guild).map_err(storage_error) {
+					Ok(Some(_)) => self.leaderboard(guild, metric, since.unwrap_or(i64::MIN)).map_err(storage_error),
+					Ok(None) => Err(BotError::NotBound),
+					Err(e) => Err(e),
+				};
+				let _ = resp.send(result);
 			}
 		};
 	}
 
-	pub async fn handle_game_state(&mut self, state: GameState) {
-		let hero = match state.heroes {
-			None => return,
-			Some(heroes) => {
-				match heroes {
-					GameHeroes::Spectating(_) => return,
-					GameHeroes::Playing(hero) => hero,
+	fn set_events(&mut self, guild: GuildId, kind: Option<EventKind>, enabled: bool) -> Result<EventFilter, BotError> {
+		let (channel, mut filter) = match self.storage.guild_channel(guild).map_err(storage_error)? {
+			None => return Err(BotError::NotBound),
+			Some(channel) => (channel, self.storage.guild_events(guild).map_err(storage_error)?.unwrap_or_default()),
+		};
+
+		match kind {
+			Some(kind) => filter.set(kind, enabled),
+			None => {
+				for (_, _, kind) in EventKind::ALL {
+					filter.set(*kind, enabled);
 				}
 			}
-		};
+		}
+
+		self.storage.set_guild_events(guild, filter).map_err(storage_error)?;
 
+		// Posts already up in the guild's channel pick up the change on their next update.
+		for live in self.matches.values_mut() {
+			for post in &mut live.posts {
+				if post.channel == channel {
+					post.events = filter;
+				}
+			}
+		}
+
+		return Ok(filter);
+	}
+
+	fn leaderboard(&self, guild: GuildId, metric: Metric, since: i64) -> Result<Vec<(UserId, Stats)>, StorageError> {
+		let mut ranked = Vec::new();
+
+		for user in self.storage.guild_users(guild)? {
+			let stats = Stats::from_matches(&self.storage.user_matches_since(user, since)?);
+			if let Some(value) = metric.value(&stats) {
+				ranked.push((value, user, stats));
+			}
+		}
+
+		ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+		ranked.truncate(LEADERBOARD_SIZE);
+
+		return Ok(ranked.into_iter().map(|(_, user, stats)| (user, stats)).collect());
+	}
+
+	pub async fn handle_game_state(&mut self, state: GameState) {
 		let map = match state.map {
 			None => return,
 			Some(x) => x,
@@ -233,18 +451,40 @@ impl Bot {
 			},
 		};
 
-		let player_info = match state.players {
-			None => return,
-			Some(players) => {
-				match players {
-					GamePlayers::Spectating(_) => return,
-					GamePlayers::Playing(player) => {
-						player
-					}
-				}
+		match (state.heroes, state.players) {
+			(Some(GameHeroes::Playing(hero)), Some(GamePlayers::Playing(player_info))) => {
+				let items = match state.items {
+					Some(GameItems::Playing(items)) => Some(items),
+					_ => None,
+				};
+
+				let abilities = match state.abilities {
+					Some(GameAbilities::Playing(abilities)) => Some(abilities),
+					_ => None,
+				};
+
+				self.handle_player(token, Arc::new(map), player_info, hero, items, abilities).await;
 			}
-		};
+			(Some(GameHeroes::Spectating(heroes)), Some(GamePlayers::Spectating(players))) => {
+				let items = match state.items {
+					Some(GameItems::Spectating(items)) => items,
+					_ => HashMap::new(),
+				};
+
+				let abilities = match state.abilities {
+					Some(GameAbilities::Spectating(abilities)) => abilities,
+					_ => HashMap::new(),
+				};
+
+				let players = spectate::spectated_players(heroes, players, items, abilities);
+				self.handle_spectator(token, Arc::new(map), players).await;
+			}
+			_ => return,
+		}
+	}
 
+	/// An update from a player's own client.
+	async fn handle_player(&mut self, token: Ulid, map: Arc<Map>, player_info: PlayerInformation, hero: Hero, items: Option<Items>, abilities: Option<Abilities>) {
 		let steam_id = match player_info.steamid.parse() {
 			Ok(x) => x,
 			Err(_) => return,
@@ -255,7 +495,15 @@ impl Bot {
 			steam_id,
 		};
 
-		match self.save.users.get(&user_info) {
+		let user_id = match self.storage.find_user(&user_info) {
+			Ok(user_id) => user_id,
+			Err(e) => {
+				log::error!("Failed to look up user {:?}! `{}`", user_info, e);
+				return;
+			}
+		};
+
+		match user_id {
 			None => return,
 			Some(user_id) => {
 				let match_id = match map.match_id.parse() {
@@ -269,82 +517,766 @@ impl Bot {
 					map,
 					player_info,
 					hero,
+					items,
+					abilities,
 					match_id,
 					user_info,
-					user_id: user_id.clone(),
+					user_id,
+					spectated: false,
 				};
 
-				let game = self.games.get_mut(&steam_id);
-				match game {
-					Some(game) => {
-						if game.match_id == match_id {
-							for message in &mut game.messages {
-								message.edit(&self.cah.http, |a| {
-									a.embed(|b| {
-										build_message(b, &game_data)
-									})
-								}).await.unwrap();
-							}
-						} else {
-							log::debug!("Found an old match for user {:?} with match ID {}. Updating to new match ID {}.", user_info, game.match_id, match_id);
-							self.new_messages(game_data).await;
-						}
-					}
-					None => {
-						log::info!("Creating new match for user {:?} with match ID {}.", user_info, match_id);
-						self.new_messages(game_data).await;
+				self.update_player(game_data).await;
+			}
+		}
+	}
+
+	/// An update from a registered user spectating a match. Does nothing unless they opted in with `/spectate`.
+	async fn handle_spectator(&mut self, token: Ulid, map: Arc<Map>, players: Vec<Spectated>) {
+		let match_id = match map.match_id.parse() {
+			Ok(match_id) => match_id,
+			Err(_) => return,
+		};
+
+		let spectator = match self.storage.token_user(token) {
+			Ok(Some(user)) => user,
+			Ok(None) => return,
+			Err(e) => {
+				log::error!("Failed to look up the user with token {}! `{}`", token, e);
+				return;
+			}
+		};
+
+		let mode = match self.storage.spectate_mode(spectator) {
+			Ok(mode) => mode,
+			Err(e) => {
+				log::error!("Failed to look up the spectate mode of {}! `{}`", spectator, e);
+				return;
+			}
+		};
+
+		if mode == SpectateMode::Off {
+			log::trace!("Ignoring spectated match {} from {}", match_id, spectator);
+			return;
+		}
+
+		if mode == SpectateMode::Scoreboard {
+			self.update_scoreboard(spectator, &map, match_id, &players).await;
+		}
+
+		for player in players {
+			let steam_id = match player.player_info.steamid.parse() {
+				Ok(x) => x,
+				Err(_) => continue,
+			};
+
+			let user_id = match self.storage.steam_user(steam_id) {
+				Ok(Some(user_id)) => user_id,
+				Ok(None) => continue,
+				Err(e) => {
+					log::error!("Failed to look up Steam account {}! `{}`", steam_id, e);
+					continue;
+				}
+			};
+
+			// Heroes are still being picked.
+			let hero = match player.hero {
+				Some(hero) if hero.name.is_some() => hero,
+				_ => continue,
+			};
+
+			log::debug!("User {} is spectating match {} with {} in it", spectator, match_id, user_id);
+
+			let game_data = GameData {
+				map: map.clone(),
+				player_info: player.player_info,
+				hero,
+				items: player.items,
+				abilities: player.abilities,
+				match_id,
+				user_info: UserInfo {
+					token,
+					steam_id,
+				},
+				user_id,
+				spectated: true,
+			};
+
+			self.update_player(game_data).await;
+		}
+	}
+
+	/// Feeds a player's latest state into their match.
+	async fn update_player(&mut self, game_data: GameData) {
+		let steam_id = game_data.user_info.steam_id;
+		let match_id = game_data.match_id;
+		let user_info = game_data.user_info;
+		let spectated = game_data.spectated;
+
+		if self.finished.get(&steam_id) == Some(&match_id) {
+			log::trace!("Ignoring update for finished match {}", match_id);
+			return;
+		}
+
+		let decided = game_data.map.game_state == DotaGameRulesState::PostGame && game_data.map.win_team != Team::None;
+
+		if self.spectated_finished.get(&steam_id) == Some(&match_id) {
+			if spectated {
+				return;
+			}
+
+			// The posts already show how it ended, so only the result is left to record.
+			if !self.matches.contains_key(&match_id) {
+				if decided {
+					record_result(self.storage.as_mut(), &game_data);
+					self.spectated_finished.remove(&steam_id);
+					self.finished.insert(steam_id, match_id);
+				}
+				return;
+			}
+		}
+
+		if let Some(old) = self.playing.get(&steam_id).copied().filter(|old| *old != match_id) {
+			// Spectators watch on a delay, so this is the player's own client having moved on.
+			if game_data.spectated {
+				return;
+			}
+
+			log::debug!("Found an old match for user {:?} with match ID {}. Updating to new match ID {}.", user_info, old, match_id);
+			self.end_player(steam_id);
+		}
+
+		match self.matches.get_mut(&match_id).and_then(|live| live.players.get_mut(&steam_id)) {
+			Some(player) => {
+				let same_source = player.data.same_source(&game_data);
+
+				// Spectators watch on a delay, so another client's state from before the one shown is old news.
+				if !same_source && game_data.map.clock_time < player.data.map.clock_time {
+					return;
+				}
+
+				// The player's own client is fresher than any spectator's.
+				if spectated && !player.data.spectated && !player.ended && player.last_update.elapsed() < OWN_CLIENT_PRIORITY {
+					return;
+				}
+
+				if same_source {
+					for event in player.tracker.detect(&player.data, &game_data) {
+						log::debug!("User {:?} in match {}: {}", user_info, match_id, event.text);
+						player.events.push(event);
 					}
+				} else {
+					// Diffing two clients' states would announce whatever one of them had already seen.
+					log::debug!("User {:?} in match {} is now reported by another client", user_info, match_id);
+					player.tracker = EventTracker::default();
+				}
+
+				// A client that went quiet and came back picks up where it left off.
+				if player.ended {
+					log::info!("User {:?} is reporting match {} again", user_info, match_id);
+					player.ended = false;
+				}
+
+				player.data = game_data;
+				player.last_update = Instant::now();
+				self.playing.insert(steam_id, match_id);
+			}
+			None => {
+				log::info!("Creating new match for user {:?} with match ID {}.", user_info, match_id);
+				if !self.join_match(game_data).await {
+					return;
 				}
 			}
 		}
+
+		self.queue_updates(match_id, steam_id);
+
+		if decided {
+			// Only the player's own client settles the match. A spectator's view of the end just finishes the posts.
+			if spectated {
+				self.spectated_finished.insert(steam_id, match_id);
+			} else {
+				self.spectated_finished.remove(&steam_id);
+				self.finished.insert(steam_id, match_id);
+			}
+			self.end_player(steam_id);
+		}
 	}
 
-	async fn new_messages(&mut self, game_data: GameData) {
-		match self.save.tracks.get(&game_data.user_id) {
-			None => {
-				log::trace!("User {:?} has no tracked channels", game_data.user_info);
-				self.games.remove(&game_data.user_info.steam_id);
+	/// Posts or updates the spectator's scoreboard for the match, finishing it once the match is decided.
+	async fn update_scoreboard(&mut self, spectator: UserId, map: &Map, match_id: u64, players: &[Spectated]) {
+		let mut embed = CreateEmbed::default();
+		spectate::build_scoreboard(&mut embed, map, match_id, players);
+
+		let decided = map.game_state == DotaGameRulesState::PostGame && map.win_team != Team::None;
+
+		if let Some(scoreboard) = self.scoreboards.get_mut(&match_id) {
+			if scoreboard.spectator != spectator {
+				return;
+			}
+
+			if decided {
+				for (_, message) in &scoreboard.posts {
+					self.updater.queue_last(*message, embed.clone());
+				}
+				self.scoreboards.remove(&match_id);
+			} else {
+				for (_, message) in &scoreboard.posts {
+					self.updater.queue(*message, embed.clone());
+				}
+				scoreboard.embed = embed;
+				scoreboard.last_update = Instant::now();
+			}
+
+			return;
+		}
+
+		// Starting one this late would only repeat the result.
+		if map.game_state == DotaGameRulesState::PostGame {
+			return;
+		}
+
+		let channels = match self.storage.track_channels(spectator) {
+			Ok(channels) => channels,
+			Err(e) => {
+				log::error!("Failed to look up tracked channels for user {}! `{}`", spectator, e);
 				return;
 			}
-			Some(tracks) => {
-				let mut messages = Vec::new();
+		};
+
+		if channels.is_empty() {
+			log::trace!("Spectator {} has no tracked channels", spectator);
+			return;
+		}
 
-				for channel in tracks {
-					let message = channel.send_message(&self.cah.http, |a| {
-						a.embed(|b| {
-							build_message(b, &game_data)
-						})
-					}).await;
+		log::info!("Posting a scoreboard for match {} spectated by {}", match_id, spectator);
 
-					match message {
-						Ok(message) => messages.push(message),
-						Err(err) => log::error!("Error sending new message! `{}`", err),
-					}
+		let mut posts = Vec::new();
+
+		for (channel, _) in channels {
+			let message = channel.send_message(&self.cah.http, |a| {
+				a.set_embed(embed.clone())
+			}).await;
+
+			match message {
+				Ok(message) => {
+					self.updater.add(channel, message.id);
+					posts.push((channel, message.id));
 				}
+				Err(err) => log::error!("Error sending new message! `{}`", err),
+			}
+		}
 
-				let game_posts = GamePosts {
-					match_id: game_data.match_id,
-					messages
-				};
+		self.scoreboards.insert(match_id, Scoreboard {
+			spectator,
+			posts,
+			last_update: Instant::now(),
+			embed,
+		});
+	}
+
+	/// Adds the player to their match, joining the posts already up in their guilds' channels and sending new ones
+	/// everywhere else. Returns `false` if they aren't tracked anywhere.
+	async fn join_match(&mut self, game_data: GameData) -> bool {
+		let channels = match self.storage.track_channels(game_data.user_id) {
+			Ok(channels) => channels,
+			Err(e) => {
+				log::error!("Failed to look up tracked channels for user {}! `{}`", game_data.user_id, e);
+				return false;
+			}
+		};
+
+		if channels.is_empty() {
+			log::trace!("User {:?} has no tracked channels", game_data.user_info);
+			return false;
+		}
+
+		let steam_id = game_data.user_info.steam_id;
+		let match_id = game_data.match_id;
+
+		let posted = self.matches.get(&match_id)
+			.map(|live| live.posts.iter().map(|post| post.channel).collect::<Vec<_>>())
+			.unwrap_or_default();
+
+		let mut posts = Vec::new();
+
+		for &(channel, filter) in &channels {
+			if posted.contains(&channel) {
+				continue;
+			}
+
+			let message = channel.send_message(&self.cah.http, |a| {
+				a.embed(|b| {
+					build_message(b, &game_data, &[])
+				})
+			}).await;
+
+			match message {
+				Ok(message) => {
+					self.updater.add(channel, message.id);
+					posts.push(Post {
+						channel,
+						message: message.id,
+						events: filter,
+						players: Vec::new(),
+					});
+				}
+				Err(err) => log::error!("Error sending new message! `{}`", err),
+			}
+		}
+
+		let live = self.matches.entry(match_id).or_default();
+		live.posts.extend(posts);
+
+		for post in &mut live.posts {
+			if channels.iter().any(|(channel, _)| *channel == post.channel) && !post.players.contains(&steam_id) {
+				post.players.push(steam_id);
+			}
+		}
+
+		live.players.insert(steam_id, PlayerGame::new(game_data));
+		self.playing.insert(steam_id, match_id);
+
+		return true;
+	}
+
+	/// Queues a fresh render of every post the player is shown in.
+	fn queue_updates(&mut self, match_id: u64, steam_id: SteamId) {
+		if let Some(live) = self.matches.get(&match_id) {
+			for post in live.posts.iter().filter(|post| post.players.contains(&steam_id)) {
+				self.updater.queue(post.message, live.render(post));
+			}
+		}
+	}
+
+	/// Records the player's current match and marks them done in it. If the client stopped reporting before the
+	/// match was decided, it is recorded without a result. Matches last seen through a spectator aren't recorded.
+	fn end_player(&mut self, steam_id: SteamId) {
+		let match_id = match self.playing.remove(&steam_id) {
+			Some(match_id) => match_id,
+			None => return,
+		};
+
+		let player = match self.matches.get_mut(&match_id).and_then(|live| live.players.get_mut(&steam_id)) {
+			Some(player) if !player.ended => player,
+			_ => return,
+		};
+
+		player.ended = true;
+
+		record_result(self.storage.as_mut(), &player.data);
+
+		self.refresh_match(match_id);
+	}
+
+	/// Takes the player out of their match without recording it, dropping the posts that only showed them.
+	fn drop_player(&mut self, steam_id: SteamId) {
+		let match_id = match self.playing.remove(&steam_id) {
+			Some(match_id) => match_id,
+			None => return,
+		};
+
+		if let Some(live) = self.matches.get_mut(&match_id) {
+			live.players.remove(&steam_id);
+
+			for post in &mut live.posts {
+				post.players.retain(|player| *player != steam_id);
+				if post.players.is_empty() {
+					self.updater.remove(post.message);
+				}
+			}
+
+			live.posts.retain(|post| !post.players.is_empty());
+		}
+
+		self.refresh_match(match_id);
+	}
 
-				self.games.insert(game_data.user_info.steam_id, game_posts);
+	/// Re-renders the match's posts. Once nobody in it is still playing, they get their final edit and the match is
+	/// forgotten.
+	fn refresh_match(&mut self, match_id: u64) {
+		let live = match self.matches.get(&match_id) {
+			Some(live) => live,
+			None => return,
+		};
+
+		if live.players.is_empty() {
+			self.matches.remove(&match_id);
+		} else if live.is_over() {
+			for post in &live.posts {
+				self.updater.queue_last(post.message, live.render(post));
 			}
+			self.matches.remove(&match_id);
+		} else {
+			for post in &live.posts {
+				self.updater.queue(post.message, live.render(post));
+			}
+		}
+	}
+
+	/// Ends players whose client has gone quiet, e.g. because the game was closed or lost its connection, and
+	/// scoreboards whose spectator has.
+	fn finish_stale_matches(&mut self) {
+		let stale = self.matches.values()
+			.flat_map(|live| live.players.iter())
+			.filter(|(_, player)| !player.ended && player.last_update.elapsed() > STALE_TIMEOUT)
+			.map(|(steam_id, _)| *steam_id)
+			.collect::<Vec<_>>();
+
+		for steam_id in stale {
+			log::info!("No updates from {} in {:?}, assuming they disconnected", steam_id, STALE_TIMEOUT);
+			self.end_player(steam_id);
 		}
+
+		let updater = &mut self.updater;
+		self.scoreboards.retain(|match_id, scoreboard| {
+			if scoreboard.last_update.elapsed() <= STALE_TIMEOUT {
+				return true;
+			}
+			log::info!("Spectator {} stopped reporting match {}", scoreboard.spectator, match_id);
+			for (_, message) in &scoreboard.posts {
+				updater.queue_last(*message, scoreboard.embed.clone());
+			}
+			return false;
+		});
+	}
+}
+
+/// Logs a storage failure and hides the details from the caller.
+fn storage_error(e: StorageError) -> BotError {
+	log::error!("Storage error! `{}`", e);
+	return BotError::Storage;
+}
+
+/// Saves the player's result. Spectated data is only ever shown, never recorded, as anyone spectating could have
+/// sent it.
+fn record_result(storage: &mut dyn Storage, data: &GameData) {
+	if data.spectated {
+		log::info!("Not recording match {} for user {} from spectated data", data.match_id, data.user_id);
+		return;
+	}
+
+	let record = match_record(data);
+
+	log::info!("Match {} for user {} ended. Won: {:?}", data.match_id, record.user, record.won);
+
+	if let Err(e) = storage.record_match(record) {
+		log::error!("Failed to record match {}! `{}`", data.match_id, e);
+	}
+}
+
+/// Formats a number of seconds as `m:ss`, with a leading `-` for times before the horn.
+pub fn format_clock(seconds: i32) -> String {
+	let sign = if seconds < 0 { "-" } else { "" };
+	let seconds = seconds.unsigned_abs();
+	return format!("{}{}:{:02}", sign, seconds / 60, seconds % 60);
+}
+
+fn phase_name(state: DotaGameRulesState) -> &'static str {
+	return match state {
+		DotaGameRulesState::HeroSelection => "Hero Selection",
+		DotaGameRulesState::StrategyTime => "Strategy",
+		DotaGameRulesState::PreGame => "Pre-Game",
+		DotaGameRulesState::InProgress => "In Progress",
+		DotaGameRulesState::PostGame => "Post-Game",
+		_ => "Loading",
+	};
+}
+
+/// Adds the clock with the time of day, the game phase, the score and any ward purchase cooldown.
+/// GSI doesn't report Roshan's state, so there's no timer for him.
+fn map_fields(e: &mut CreateEmbed, map: &Map) {
+	let time_of_day = if map.nightstalker_night {
+		"Night Stalker night"
+	} else if map.daytime {
+		"Day"
+	} else {
+		"Night"
+	};
+	e.field("Time", format!("{} ({})", format_clock(map.clock_time), time_of_day), true);
+
+	let mut phase = phase_name(map.game_state).to_string();
+	if map.paused {
+		phase.push_str(" (paused)");
+	}
+	e.field("Phase", phase, true);
+
+	e.field("Radiant / Dire", format!("{}/{}", map.radiant_score, map.dire_score), true);
+
+	if let Some(cooldown) = map.ward_purchase_cooldown.filter(|cooldown| *cooldown > 0) {
+		e.field("Ward purchase cooldown", format!("{}s", cooldown), true);
 	}
 }
 
-fn build_message<'a, 'b>(e: &'a mut CreateEmbed, data: &'b GameData) -> &'a mut CreateEmbed {
+/// Whether the player won, or `None` until the map reports a winner.
+fn match_result(data: &GameData) -> Option<bool> {
+	return match data.map.win_team {
+		Team::None => None,
+		team => Some(team == data.player_info.team_name),
+	};
+}
+
+/// Snapshots the player's current stats.
+fn match_record(data: &GameData) -> MatchRecord {
+	let player = &data.player_info;
+
+	return MatchRecord {
+		match_id: data.match_id,
+		steam_id: data.user_info.steam_id,
+		user: data.user_id,
+		hero: data.hero.name.clone().unwrap_or_default(),
+		kills: player.kills,
+		deaths: player.deaths,
+		assists: player.assists,
+		last_hits: player.last_hits,
+		denies: player.denies,
+		gpm: player.gpm,
+		xpm: player.xpm,
+		net_worth: player.net_worth,
+		duration: data.map.clock_time,
+		team: player.team_name.to_string(),
+		won: match_result(data),
+		ended_at: Utc::now().timestamp(),
+	};
+}
+
+/// The final state of a match, replacing the live embed once it ends.
+fn build_summary<'a, 'b>(e: &'a mut CreateEmbed, data: &'b GameData, won: Option<bool>) -> &'a mut CreateEmbed {
 	let map = &data.map;
 	let player = &data.player_info;
 	let hero = &data.hero;
 
-	e.title(format!("{} is playing a match on {}!", player.name, player.team_name));
+	let (header, colour) = match won {
+		Some(true) => ("VICTORY", Colour::DARK_GREEN),
+		Some(false) => ("DEFEAT", Colour::RED),
+		None => ("MATCH ENDED", Colour::LIGHT_GREY),
+	};
 
-	e.field("Time", map.clock_time, true);
+	e.title(format!("{} - {} on {}", header, player.name, player.team_name));
+	e.colour(colour);
+
+	if won.is_none() {
+		e.description("The client stopped reporting before the match was decided.");
+	}
+
+	if let Some(name) = &hero.name {
+		hero_field(e, name, true);
+	}
+
+	if let Some(level) = hero.level {
+		e.field("Level", level, true);
+	}
+
+	e.field("Duration", format_clock(map.clock_time), true);
 
 	e.field("Radiant / Dire", format!("{}/{}", map.radiant_score, map.dire_score), true);
 
-	e.field("Hero", hero.name.as_ref().unwrap(), false);
+	e.field("K/D/A", format!("{}/{}/{}", player.kills, player.deaths, player.assists), true);
+
+	e.field("CS/DN", format!("{}/{}", player.last_hits, player.denies), true);
+
+	e.field("XPM/GPM", format!("{}/{}", player.xpm, player.gpm), true);
+
+	if let Some(net_worth) = player.net_worth {
+		e.field("Net Worth", net_worth, true);
+	}
+
+	e.footer(|f| {
+		f.text(format!("Match ID: {}", data.match_id))
+	});
+
+	e.timestamp(Utc::now());
+
+	return e;
+}
+
+/// Discord rejects embeds with a longer field value.
+const FIELD_LIMIT: usize = 1024;
+
+/// `name` as a link to its icon, if it has one.
+fn icon_link(name: String, icon: Option<String>) -> String {
+	return match icon {
+		Some(icon) => format!("[{}]({})", name, icon),
+		None => name,
+	};
+}
+
+/// Joins the lines `line` builds with icon links, or without them if that doesn't fit in a field.
+fn icon_lines<T>(items: &[T], line: impl Fn(&T, bool) -> String) -> String {
+	let linked = items.iter().map(|item| line(item, true)).collect::<Vec<_>>().join("\n");
+	if linked.len() <= FIELD_LIMIT {
+		return linked;
+	}
+	return items.iter().map(|item| line(item, false)).collect::<Vec<_>>().join("\n");
+}
+
+/// How an item reads in the embed, e.g. `Magic Wand (12) - 8s` for one with charges on cooldown, with the name linked
+/// to its icon if `linked`.
+fn item_label(item: &Item, linked: bool) -> String {
+	let mut label = metadata::item_name(&item.name);
+	if linked {
+		label = icon_link(label, metadata::item_icon_url(&item.name));
+	}
+
+	if let Some(charges) = item.charges.filter(|c| *c > 0) {
+		label.push_str(&format!(" ({})", charges));
+	}
+
+	if let Some(cooldown) = item.cooldown.filter(|c| *c > 0) {
+		label.push_str(&format!(" - {}s", cooldown));
+	}
+
+	return label;
+}
+
+/// The inventory, backpack, neutral item and TP scroll.
+fn items_fields<'b>(e: &mut CreateEmbed, items: &'b Items) {
+	let filled = |slots: &[&'b Item]| slots.iter().copied().filter(|item| item.name != "empty").collect::<Vec<_>>();
+	let label = |item: &&Item, linked| item_label(item, linked);
+
+	let inventory = filled(&[&items.slot0, &items.slot1, &items.slot2, &items.slot3, &items.slot4, &items.slot5]);
+	e.field("Items", if inventory.is_empty() { "None".to_owned() } else { icon_lines(&inventory, label) }, true);
+
+	let backpack = filled(&[&items.slot6, &items.slot7, &items.slot8]);
+	if !backpack.is_empty() {
+		e.field("Backpack", icon_lines(&backpack, label), true);
+	}
+
+	if items.neutral0.name != "empty" {
+		e.field("Neutral", item_label(&items.neutral0, true), true);
+	}
+
+	if items.teleport0.name != "empty" {
+		let tp = match items.teleport0.cooldown.filter(|c| *c > 0) {
+			Some(cooldown) => format!("On cooldown ({}s)", cooldown),
+			None => "Ready".to_owned(),
+		};
+		e.field("TP Scroll", tp, true);
+	}
+}
+
+/// The hero's abilities in slot order, leaving out talents and hidden placeholders.
+fn sorted_abilities(abilities: &Abilities) -> Vec<&Ability> {
+	let mut sorted = abilities.iter()
+		.map(|(slot, ability)| (slot.trim_start_matches("ability").parse::<u32>().unwrap_or(u32::MAX), ability))
+		.collect::<Vec<_>>();
+	sorted.sort_by_key(|(slot, _)| *slot);
+	return sorted.into_iter().map(|(_, ability)| ability).collect();
+}
+
+/// Each ability's level and cooldown, with the ultimate in bold and marked once it is ready.
+fn abilities_field(e: &mut CreateEmbed, abilities: &Abilities) {
+	let shown = sorted_abilities(abilities).into_iter()
+		.filter(|a| a.name != "generic_hidden" && !a.name.starts_with("special_bonus_"))
+		.collect::<Vec<_>>();
+
+	if shown.is_empty() {
+		return;
+	}
+
+	let lines = icon_lines(&shown, |a, linked| {
+		let mut name = metadata::ability_name(&a.name);
+		if linked {
+			name = icon_link(name, Some(metadata::ability_icon_url(&a.name)));
+		}
+		let mut line = if a.ultimate { format!("**{}** ({})", name, a.level) } else { format!("{} ({})", name, a.level) };
+
+		if a.cooldown > 0 {
+			line.push_str(&format!(" - {}s", a.cooldown));
+		} else if a.ultimate && a.level > 0 && a.can_cast {
+			line.push_str(" - Ready");
+		}
+
+		line
+	});
+
+	e.field("Abilities", lines, true);
+}
+
+/// The side picked at each talent tier. GSI only says which of the eight talent slots are taken, two per tier with the
+/// right-hand one first, and nothing reliably names the talents behind them.
+fn talents_field(e: &mut CreateEmbed, hero: &Hero) {
+	let picked = [
+		hero.talent_1, hero.talent_2, hero.talent_3, hero.talent_4,
+		hero.talent_5, hero.talent_6, hero.talent_7, hero.talent_8,
+	];
+
+	let lines = picked.chunks(2)
+		.zip([10, 15, 20, 25])
+		.filter_map(|(pair, tier)| {
+			let side = match pair {
+				[Some(true), _] => "Right",
+				[_, Some(true)] => "Left",
+				_ => return None,
+			};
+			Some(format!("{}: {}", tier, side))
+		})
+		.collect::<Vec<_>>();
+
+	if !lines.is_empty() {
+		e.field("Talents", lines.join("\n"), true);
+	}
+}
+
+/// Adds the hero's name, with its primary attribute and portrait if it is in the bundled table.
+fn hero_field(e: &mut CreateEmbed, name: &str, inline: bool) {
+	match metadata::hero(name) {
+		Some(hero) => {
+			e.field("Hero", format!("{} ({})", hero.localized_name, hero.primary_attr), inline);
+			e.thumbnail(hero.portrait_url());
+		}
+		None => {
+			e.field("Hero", hero_name(name), inline);
+		}
+	}
+}
+
+/// Describes a dead hero's respawn and buyback, e.g. `Dead, respawning in 34s (buyback ready/1832g)`.
+/// `None` while the hero is alive or GSI doesn't say.
+fn death_status(hero: &Hero, gold: u32) -> Option<String> {
+	if hero.alive != Some(false) {
+		return None;
+	}
+
+	let mut status = match hero.respawn_seconds {
+		Some(seconds) if seconds > 0 => format!("Dead, respawning in {}s", seconds),
+		_ => "Dead".to_string(),
+	};
+
+	if let Some(cost) = hero.buyback_cost {
+		let buyback = match hero.buyback_cooldown {
+			Some(cooldown) if cooldown > 0 => format!("buyback in {}s/{}g", cooldown, cost),
+			_ if gold < cost => format!("buyback {}g short/{}g", cost - gold, cost),
+			_ => format!("buyback ready/{}g", cost),
+		};
+		status.push_str(&format!(" ({})", buyback));
+	}
+
+	return Some(status);
+}
+
+/// The latest events a post with the given filter should list, oldest first.
+fn shown_events(events: &[GameEvent], filter: EventFilter) -> Vec<&GameEvent> {
+	let mut shown = events.iter()
+		.rev()
+		.filter(|event| filter.allows(event.kind))
+		.take(SHOWN_EVENTS)
+		.collect::<Vec<_>>();
+	shown.reverse();
+	return shown;
+}
+
+fn build_message<'a, 'b>(e: &'a mut CreateEmbed, data: &'b GameData, events: &[&GameEvent]) -> &'a mut CreateEmbed {
+	let map = &data.map;
+	let player = &data.player_info;
+	let hero = &data.hero;
+
+	e.title(format!("{} is playing a match on {}!", player.name, player.team_name));
+
+	map_fields(e, map);
+
+	if let Some(name) = &hero.name {
+		hero_field(e, name, false);
+	}
 
 	if hero.level.is_some() {
 		e.field("Level", hero.level.unwrap(), true);
@@ -354,7 +1286,10 @@ fn build_message<'a, 'b>(e: &'a mut CreateEmbed, data: &'b GameData) -> &'a mut
 
 	e.field("\u{200b}", "\u{200b}", false); // New line.
 
-	if hero.health.is_some() && hero.max_health.is_some() && hero.mana.is_some() && hero.max_mana.is_some() {
+	if let Some(status) = death_status(hero, player.gold) {
+		e.field("Status", status, false);
+		e.colour(Colour::RED);
+	} else if hero.health.is_some() && hero.max_health.is_some() && hero.mana.is_some() && hero.max_mana.is_some() {
 		e.field("Health", format!("{}/{}", hero.health.unwrap(), hero.max_health.unwrap()), true);
 		e.field("Mana", format!("{}/{}", hero.mana.unwrap(), hero.max_mana.unwrap()), true);
 	}
@@ -367,6 +1302,34 @@ fn build_message<'a, 'b>(e: &'a mut CreateEmbed, data: &'b GameData) -> &'a mut
 
 	e.field("XPM/GPM", format!("{}/{}", player.xpm, player.gpm), true);
 
+	if let Some(items) = &data.items {
+		items_fields(e, items);
+	}
+
+	if let Some(abilities) = &data.abilities {
+		abilities_field(e, abilities);
+	}
+
+	talents_field(e, hero);
+
+	if let (Some(scepter), Some(shard)) = (hero.aghanims_scepter, hero.aghanims_shard) {
+		let upgrades = match (scepter, shard) {
+			(true, true) => "Scepter, Shard",
+			(true, false) => "Scepter",
+			(false, true) => "Shard",
+			(false, false) => "None",
+		};
+		e.field("Aghanim's", upgrades, true);
+	}
+
+	if !events.is_empty() {
+		let lines = events.iter()
+			.map(|event| format!("`{}` {}", format_clock(event.clock), event.text))
+			.collect::<Vec<_>>()
+			.join("\n");
+		e.field("Events", lines, false);
+	}
+
 	e.footer(|f| {
 		f.text(format!("Match ID: {}", data.match_id))
 	});
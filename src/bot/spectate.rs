@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::time::Instant;
+use chrono::Utc;
+use dota::components::{Map, Team};
+use dota::components::heroes::Hero;
+use dota::components::items::Items;
+use dota::components::players::PlayerInformation;
+use serde::{Deserialize, Serialize};
+use serenity::builder::CreateEmbed;
+use serenity::model::id::{ChannelId, MessageId, UserId};
+use serenity::utils::Colour;
+use crate::metadata::hero_name;
+use super::{map_fields, Abilities};
+
+/// What a registered user's client does with the matches it spectates.
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SpectateMode {
+	/// Spectated matches are ignored.
+	#[default]
+	Off,
+	/// Feeds the live posts of tracked players in the match.
+	Players,
+	/// Also posts a scoreboard of all ten players.
+	Scoreboard,
+}
+
+impl SpectateMode {
+	/// Choice values for the `/spectate` command, with their labels.
+	pub const ALL: &'static [(&'static str, &'static str, SpectateMode)] = &[
+		("off", "Off", SpectateMode::Off),
+		("players", "Update tracked players", SpectateMode::Players),
+		("scoreboard", "Update tracked players and post a scoreboard", SpectateMode::Scoreboard),
+	];
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		return SpectateMode::ALL.iter().find(|(n, _, _)| *n == name).map(|(_, _, m)| *m);
+	}
+
+	pub fn name(self) -> &'static str {
+		return SpectateMode::ALL.iter().find(|(_, _, m)| *m == self).map(|(n, _, _)| *n).unwrap_or_default();
+	}
+}
+
+/// One of the ten players in a spectated match.
+pub struct Spectated {
+	/// Where GSI lists the player, e.g. `player3`. Orders the scoreboard.
+	pub slot: String,
+	pub player_info: PlayerInformation,
+	pub hero: Option<Hero>,
+	pub items: Option<Items>,
+	pub abilities: Option<Abilities>,
+}
+
+/// Pairs up the per-team, per-slot maps GSI sends while spectating.
+pub fn spectated_players(
+	mut heroes: HashMap<String, HashMap<String, Hero>>,
+	players: HashMap<String, HashMap<String, PlayerInformation>>,
+	mut items: HashMap<String, HashMap<String, Items>>,
+	mut abilities: HashMap<String, HashMap<String, Abilities>>,
+) -> Vec<Spectated> {
+	let mut spectated = Vec::new();
+
+	for (team, players) in players {
+		let mut heroes = heroes.remove(&team).unwrap_or_default();
+		let mut items = items.remove(&team).unwrap_or_default();
+		let mut abilities = abilities.remove(&team).unwrap_or_default();
+
+		for (slot, player_info) in players {
+			spectated.push(Spectated {
+				hero: heroes.remove(&slot),
+				items: items.remove(&slot),
+				abilities: abilities.remove(&slot),
+				slot,
+				player_info,
+			});
+		}
+	}
+
+	spectated.sort_by(|a, b| a.slot.cmp(&b.slot));
+
+	return spectated;
+}
+
+/// The full-match post a spectator in [`SpectateMode::Scoreboard`] keeps up to date.
+pub struct Scoreboard {
+	pub spectator: UserId,
+	pub posts: Vec<(ChannelId, MessageId)>,
+	pub last_update: Instant,
+	/// The last render, to finish with if the spectator stops reporting.
+	pub embed: CreateEmbed,
+}
+
+pub fn build_scoreboard<'a>(e: &'a mut CreateEmbed, map: &Map, match_id: u64, players: &[Spectated]) -> &'a mut CreateEmbed {
+	match map.win_team {
+		Team::None => e.title("Live scoreboard"),
+		team => e.title(format!("{} victory", team)).colour(Colour::LIGHT_GREY),
+	};
+
+	map_fields(e, map);
+
+	for team in [Team::Radiant, Team::Dire] {
+		let lines = players.iter()
+			.filter(|p| p.player_info.team_name == team)
+			.map(|p| {
+				let info = &p.player_info;
+				let hero = p.hero.as_ref().and_then(|h| h.name.as_deref()).map(hero_name).unwrap_or_default();
+				let level = p.hero.as_ref().and_then(|h| h.level).unwrap_or_default();
+				format!(
+					"**{}** {} (Lvl {}) - {}/{}/{}, {} CS, {} GPM",
+					info.name, hero, level, info.kills, info.deaths, info.assists, info.last_hits, info.gpm,
+				)
+			})
+			.collect::<Vec<_>>();
+
+		if !lines.is_empty() {
+			e.field(team, lines.join("\n"), false);
+		}
+	}
+
+	e.footer(|f| {
+		f.text(format!("Match ID: {}", match_id))
+	});
+
+	e.timestamp(Utc::now());
+
+	return e;
+}
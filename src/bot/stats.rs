@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use crate::storage::MatchRecord;
+
+/// How many heroes `Stats::top_heroes` lists.
+const TOP_HEROES: usize = 3;
+
+/// Decided matches a user needs before they are ranked by win rate, so one lucky game doesn't top the board.
+pub const MIN_WIN_RATE_GAMES: usize = 5;
+
+/// What `/leaderboard` can rank users by.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Metric {
+	Wins,
+	WinRate,
+	AvgGpm,
+	Kills,
+	Games,
+}
+
+impl Metric {
+	/// Choice values for the command option, with their labels.
+	pub const ALL: &'static [(&'static str, &'static str, Metric)] = &[
+		("wins", "Wins", Metric::Wins),
+		("winrate", "Win Rate", Metric::WinRate),
+		("gpm", "Average GPM", Metric::AvgGpm),
+		("kills", "Total Kills", Metric::Kills),
+		("games", "Games Played", Metric::Games),
+	];
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		return Metric::ALL.iter().find(|(n, _, _)| *n == name).map(|(_, _, m)| *m);
+	}
+
+	pub fn label(self) -> &'static str {
+		return Metric::ALL.iter().find(|(_, _, m)| *m == self).map(|(_, l, _)| *l).unwrap_or_default();
+	}
+
+	/// The value to rank by, or `None` if the user doesn't qualify.
+	pub fn value(self, stats: &Stats) -> Option<f64> {
+		if stats.games == 0 {
+			return None;
+		}
+
+		return match self {
+			Metric::Wins => Some(stats.wins as f64),
+			Metric::WinRate if stats.wins + stats.losses < MIN_WIN_RATE_GAMES => None,
+			Metric::WinRate => stats.win_rate(),
+			Metric::AvgGpm => Some(stats.avg_gpm),
+			Metric::Kills => Some(stats.kills as f64),
+			Metric::Games => Some(stats.games as f64),
+		};
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct HeroStats {
+	pub hero: String,
+	pub games: usize,
+	pub wins: usize,
+}
+
+/// Aggregates over a set of recorded matches. Averages cover every match; win rate and streak only decided ones.
+#[derive(Debug, Default)]
+pub struct Stats {
+	pub games: usize,
+	pub wins: usize,
+	pub losses: usize,
+	pub kills: u64,
+	pub avg_kills: f64,
+	pub avg_deaths: f64,
+	pub avg_assists: f64,
+	pub avg_gpm: f64,
+	pub avg_xpm: f64,
+	pub top_heroes: Vec<HeroStats>,
+	/// Consecutive results ending with the most recent decided match: positive for wins, negative for losses.
+	pub streak: i32,
+}
+
+impl Stats {
+	/// Expects `matches` most recent first, as storage returns them.
+	pub fn from_matches(matches: &[MatchRecord]) -> Self {
+		let mut stats = Stats::default();
+
+		if matches.is_empty() {
+			return stats;
+		}
+
+		let mut heroes: HashMap<&str, HeroStats> = HashMap::new();
+
+		for record in matches {
+			stats.games += 1;
+			stats.kills += record.kills as u64;
+			stats.avg_deaths += record.deaths as f64;
+			stats.avg_assists += record.assists as f64;
+			stats.avg_gpm += record.gpm as f64;
+			stats.avg_xpm += record.xpm as f64;
+
+			let hero = heroes.entry(&record.hero).or_insert_with(|| HeroStats {
+				hero: record.hero.clone(),
+				..HeroStats::default()
+			});
+			hero.games += 1;
+
+			match record.won {
+				Some(true) => {
+					stats.wins += 1;
+					hero.wins += 1;
+				}
+				Some(false) => stats.losses += 1,
+				None => {}
+			}
+		}
+
+		let games = stats.games as f64;
+		stats.avg_kills = stats.kills as f64 / games;
+		stats.avg_deaths /= games;
+		stats.avg_assists /= games;
+		stats.avg_gpm /= games;
+		stats.avg_xpm /= games;
+
+		let mut heroes = heroes.into_values().collect::<Vec<_>>();
+		heroes.sort_by(|a, b| b.games.cmp(&a.games).then_with(|| b.wins.cmp(&a.wins)).then_with(|| a.hero.cmp(&b.hero)));
+		heroes.truncate(TOP_HEROES);
+		stats.top_heroes = heroes;
+
+		let mut decided = matches.iter().filter_map(|m| m.won);
+		if let Some(last) = decided.next() {
+			let length = 1 + decided.take_while(|won| *won == last).count() as i32;
+			stats.streak = if last { length } else { -length };
+		}
+
+		return stats;
+	}
+
+	/// `None` until at least one match has been decided.
+	pub fn win_rate(&self) -> Option<f64> {
+		let decided = self.wins + self.losses;
+		if decided == 0 {
+			return None;
+		}
+		return Some(self.wins as f64 / decided as f64);
+	}
+
+	/// (K + A) / D, or just K + A without any deaths.
+	pub fn kda_ratio(&self) -> f64 {
+		if self.avg_deaths == 0.0 {
+			return self.avg_kills + self.avg_assists;
+		}
+		return (self.avg_kills + self.avg_assists) / self.avg_deaths;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serenity::model::id::UserId;
+
+	use super::*;
+
+	fn record(hero: &str, kills: u32, deaths: u32, assists: u32, gpm: u32, won: Option<bool>) -> MatchRecord {
+		MatchRecord {
+			match_id: 1,
+			steam_id: 76561197960287930,
+			user: UserId(1),
+			hero: hero.to_owned(),
+			kills,
+			deaths,
+			assists,
+			last_hits: 0,
+			denies: 0,
+			gpm,
+			xpm: gpm + 100,
+			net_worth: None,
+			duration: 2400,
+			team: "radiant".to_owned(),
+			won,
+			ended_at: 0,
+		}
+	}
+
+	#[test]
+	fn empty_matches_have_no_stats() {
+		let stats = Stats::from_matches(&[]);
+		assert_eq!(stats.games, 0);
+		assert_eq!(stats.streak, 0);
+		assert_eq!(stats.win_rate(), None);
+		assert!(stats.top_heroes.is_empty());
+	}
+
+	#[test]
+	fn averages_every_match() {
+		let stats = Stats::from_matches(&[
+			record("npc_dota_hero_axe", 10, 2, 6, 600, Some(true)),
+			record("npc_dota_hero_axe", 4, 6, 10, 400, None),
+			record("npc_dota_hero_lina", 7, 4, 2, 500, Some(false)),
+		]);
+
+		assert_eq!(stats.games, 3);
+		assert_eq!((stats.wins, stats.losses), (1, 1));
+		assert_eq!(stats.kills, 21);
+		assert_eq!(stats.avg_kills, 7.0);
+		assert_eq!(stats.avg_deaths, 4.0);
+		assert_eq!(stats.avg_assists, 6.0);
+		assert_eq!(stats.avg_gpm, 500.0);
+		assert_eq!(stats.avg_xpm, 600.0);
+		assert_eq!(stats.win_rate(), Some(0.5));
+		assert_eq!(stats.kda_ratio(), 13.0 / 4.0);
+
+		let heroes: Vec<_> = stats.top_heroes.iter().map(|h| (h.hero.as_str(), h.games, h.wins)).collect();
+		assert_eq!(heroes, vec![("npc_dota_hero_axe", 2, 1), ("npc_dota_hero_lina", 1, 0)]);
+	}
+
+	#[test]
+	fn streak_skips_undecided_matches() {
+		let stats = Stats::from_matches(&[
+			record("npc_dota_hero_axe", 0, 0, 0, 0, None),
+			record("npc_dota_hero_axe", 0, 0, 0, 0, Some(true)),
+			record("npc_dota_hero_axe", 0, 0, 0, 0, None),
+			record("npc_dota_hero_axe", 0, 0, 0, 0, Some(true)),
+			record("npc_dota_hero_axe", 0, 0, 0, 0, Some(false)),
+			record("npc_dota_hero_axe", 0, 0, 0, 0, Some(true)),
+		]);
+		assert_eq!(stats.streak, 2);
+
+		let stats = Stats::from_matches(&[
+			record("npc_dota_hero_axe", 0, 0, 0, 0, Some(false)),
+			record("npc_dota_hero_axe", 0, 0, 0, 0, None),
+			record("npc_dota_hero_axe", 0, 0, 0, 0, Some(false)),
+			record("npc_dota_hero_axe", 0, 0, 0, 0, Some(false)),
+		]);
+		assert_eq!(stats.streak, -3);
+
+		let stats = Stats::from_matches(&[record("npc_dota_hero_axe", 0, 0, 0, 0, None)]);
+		assert_eq!(stats.streak, 0);
+	}
+
+	#[test]
+	fn kda_ratio_without_deaths() {
+		let stats = Stats::from_matches(&[record("npc_dota_hero_axe", 8, 0, 4, 0, Some(true))]);
+		assert_eq!(stats.kda_ratio(), 12.0);
+	}
+
+	#[test]
+	fn top_heroes_are_capped() {
+		let stats = Stats::from_matches(&[
+			record("npc_dota_hero_axe", 0, 0, 0, 0, Some(true)),
+			record("npc_dota_hero_lina", 0, 0, 0, 0, Some(true)),
+			record("npc_dota_hero_lion", 0, 0, 0, 0, Some(false)),
+			record("npc_dota_hero_bane", 0, 0, 0, 0, Some(false)),
+		]);
+
+		let heroes: Vec<_> = stats.top_heroes.iter().map(|h| h.hero.as_str()).collect();
+		assert_eq!(heroes, vec!["npc_dota_hero_axe", "npc_dota_hero_lina", "npc_dota_hero_bane"]);
+	}
+
+	#[test]
+	fn win_rate_needs_enough_decided_games() {
+		let mut matches = vec![record("npc_dota_hero_axe", 0, 0, 0, 0, None); 3];
+		matches.extend(vec![record("npc_dota_hero_axe", 0, 0, 0, 0, Some(true)); MIN_WIN_RATE_GAMES - 1]);
+		let stats = Stats::from_matches(&matches);
+		assert_eq!(Metric::WinRate.value(&stats), None);
+		assert_eq!(Metric::Games.value(&stats), Some((MIN_WIN_RATE_GAMES + 2) as f64));
+
+		matches.push(record("npc_dota_hero_axe", 0, 0, 0, 0, Some(false)));
+		let stats = Stats::from_matches(&matches);
+		assert_eq!(Metric::WinRate.value(&stats), Some((MIN_WIN_RATE_GAMES - 1) as f64 / MIN_WIN_RATE_GAMES as f64));
+	}
+
+	#[test]
+	fn metric_values() {
+		let stats = Stats::from_matches(&[
+			record("npc_dota_hero_axe", 10, 2, 6, 600, Some(true)),
+			record("npc_dota_hero_lina", 4, 6, 10, 400, Some(false)),
+		]);
+		assert_eq!(Metric::Wins.value(&stats), Some(1.0));
+		assert_eq!(Metric::AvgGpm.value(&stats), Some(500.0));
+		assert_eq!(Metric::Kills.value(&stats), Some(14.0));
+		assert_eq!(Metric::Games.value(&stats), Some(2.0));
+	}
+
+	#[test]
+	fn users_without_games_are_unranked() {
+		let stats = Stats::from_matches(&[]);
+		for (_, _, metric) in Metric::ALL {
+			assert_eq!(metric.value(&stats), None);
+		}
+	}
+
+	#[test]
+	fn metric_names_round_trip() {
+		for (name, label, metric) in Metric::ALL {
+			assert_eq!(Metric::from_name(name), Some(*metric));
+			assert_eq!(metric.label(), *label);
+		}
+		assert_eq!(Metric::from_name("deaths"), None);
+	}
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serenity::builder::CreateEmbed;
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, MessageId};
+use tokio::sync::mpsc;
+
+/// Used when `EDIT_INTERVAL` is not set.
+pub const DEFAULT_EDIT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often `Bot::run` should call [`Updater::flush`].
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Transient failures in a row before an update is given up on. The next one queued tries again from scratch.
+const MAX_ATTEMPTS: u32 = 5;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How an edit went, sent back from the task that made it.
+#[derive(Debug)]
+pub struct EditResult {
+	message: MessageId,
+	outcome: Outcome,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Outcome {
+	Done,
+	/// Worth retrying, e.g. a timeout or a Discord outage.
+	Failed,
+	/// The message was deleted, or we lost access to it.
+	Gone,
+}
+
+struct Post {
+	channel: ChannelId,
+	/// The newest embed that hasn't been sent yet. Queuing another replaces it.
+	pending: Option<CreateEmbed>,
+	/// A copy of the embed being sent, to retry with if the edit fails and nothing newer has been queued.
+	sending: Option<CreateEmbed>,
+	/// Drop the post once `pending` is delivered.
+	last: bool,
+	in_flight: bool,
+	next_edit: Instant,
+	attempts: u32,
+}
+
+/// Batches embed edits so each message is edited at most once per interval, however often its match updates.
+///
+/// Edits run in their own tasks so a slow or rate-limited route doesn't hold up the bot. Serenity's ratelimiter
+/// takes care of the per-route buckets; this only decides when an edit is worth making.
+pub struct Updater {
+	http: Arc<Http>,
+	interval: Duration,
+	posts: HashMap<MessageId, Post>,
+	result_tx: mpsc::Sender<EditResult>,
+}
+
+impl Updater {
+	pub fn new(http: Arc<Http>, interval: Duration, result_tx: mpsc::Sender<EditResult>) -> Self {
+		return Updater {
+			http,
+			interval,
+			posts: HashMap::new(),
+			result_tx,
+		};
+	}
+
+	/// Starts managing a freshly sent message. Its first edit waits out a full interval.
+	pub fn add(&mut self, channel: ChannelId, message: MessageId) {
+		self.posts.insert(message, Post {
+			channel,
+			pending: None,
+			sending: None,
+			last: false,
+			in_flight: false,
+			next_edit: Instant::now() + self.interval,
+			attempts: 0,
+		});
+	}
+
+	/// Replaces whatever was waiting to be sent to the message.
+	pub fn queue(&mut self, message: MessageId, embed: CreateEmbed) {
+		if let Some(post) = self.posts.get_mut(&message) {
+			post.pending = Some(embed);
+		}
+	}
+
+	/// Queues one more edit, after which the message is left alone.
+	pub fn queue_last(&mut self, message: MessageId, embed: CreateEmbed) {
+		if let Some(post) = self.posts.get_mut(&message) {
+			post.pending = Some(embed);
+			post.last = true;
+		}
+	}
+
+	/// Stops updating the message, dropping anything still queued for it.
+	pub fn remove(&mut self, message: MessageId) {
+		self.posts.remove(&message);
+	}
+
+	/// Starts the edits that are due.
+	pub fn flush(&mut self) {
+		let now = Instant::now();
+
+		for (message, post) in &mut self.posts {
+			if post.in_flight || post.next_edit > now {
+				continue;
+			}
+
+			let embed = match post.pending.take() {
+				Some(embed) => embed,
+				None => continue,
+			};
+
+			post.in_flight = true;
+			post.next_edit = now + self.interval;
+			post.sending = Some(embed.clone());
+
+			let http = self.http.clone();
+			let result_tx = self.result_tx.clone();
+			let (channel, message) = (post.channel, *message);
+
+			tokio::spawn(async move {
+				let outcome = match channel.edit_message(&http, message, |m| m.set_embed(embed)).await {
+					Ok(_) => Outcome::Done,
+					Err(err) => {
+						log::warn!("Error editing message {} in {}! `{}`", message, channel, err);
+						classify(&err)
+					}
+				};
+
+				let _ = result_tx.send(EditResult { message, outcome }).await;
+			});
+		}
+
+		self.posts.retain(|_, post| !post.last || post.in_flight || post.pending.is_some());
+	}
+
+	/// Applies the outcome of an edit. Returns the message if it turned out to be gone, so the caller can forget it too.
+	pub fn handle_result(&mut self, result: EditResult) -> Option<MessageId> {
+		let post = self.posts.get_mut(&result.message)?;
+
+		post.in_flight = false;
+		let sent = post.sending.take();
+
+		match result.outcome {
+			Outcome::Done => {
+				post.attempts = 0;
+			}
+			Outcome::Failed => {
+				post.attempts += 1;
+
+				if post.attempts >= MAX_ATTEMPTS {
+					log::error!("Giving up on an update to message {} after {} attempts", result.message, post.attempts);
+					post.attempts = 0;
+				} else {
+					if post.pending.is_none() {
+						post.pending = sent;
+					}
+					post.next_edit = Instant::now() + backoff(post.attempts);
+				}
+			}
+			Outcome::Gone => {
+				log::info!("Message {} is gone, no longer updating it", result.message);
+				self.posts.remove(&result.message);
+				return Some(result.message);
+			}
+		}
+
+		return None;
+	}
+}
+
+fn backoff(attempts: u32) -> Duration {
+	return Duration::from_secs(1 << attempts.min(6)).min(MAX_BACKOFF);
+}
+
+fn classify(err: &serenity::Error) -> Outcome {
+	match err {
+		serenity::Error::Http(e) => match e.status_code().map(|s| s.as_u16()) {
+			Some(403) | Some(404) => Outcome::Gone,
+			_ => Outcome::Failed,
+		},
+		_ => Outcome::Failed,
+	}
+}
@@ -0,0 +1,119 @@
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::id::UserId;
+use serenity::model::mention::Mentionable;
+use tokio::sync::{mpsc, oneshot};
+use crate::bot::{self, BotError, BotRequest};
+use crate::metadata;
+use crate::storage::MatchRecord;
+
+pub const DEFAULT_LIMIT: u64 = 5;
+pub const MAX_LIMIT: u64 = 10;
+
+/// Prefix of the custom IDs on the paging buttons, so `interaction_create` can route them here.
+pub const BUTTON_PREFIX: &str = "history:";
+
+pub struct HistoryPage {
+	pub user: UserId,
+	pub page: usize,
+	pub limit: usize,
+	pub matches: Vec<MatchRecord>,
+	pub has_next: bool,
+}
+
+/// Asks the bot for one page of the user's history. Fetches one extra match to know whether there is a next page.
+pub async fn fetch_page(bot_req_tx: &mpsc::Sender<BotRequest>, user: UserId, page: usize, limit: usize) -> Result<HistoryPage, BotError> {
+	let (tx, rx) = oneshot::channel();
+	let request = BotRequest::MatchHistory {
+		user,
+		offset: page * limit,
+		limit: limit + 1,
+		resp: tx,
+	};
+
+	log::trace!("Sending bot request");
+
+	bot_req_tx.send(request).await.unwrap();
+
+	let mut matches = rx.await.unwrap()?;
+
+	log::trace!("Received bot response");
+
+	let has_next = matches.len() > limit;
+	matches.truncate(limit);
+
+	return Ok(HistoryPage {
+		user,
+		page,
+		limit,
+		matches,
+		has_next,
+	});
+}
+
+/// Button custom IDs look like `history:<user>:<page>:<limit>`.
+pub fn parse_button(custom_id: &str) -> Option<(UserId, usize, usize)> {
+	let mut parts = custom_id.strip_prefix(BUTTON_PREFIX)?.split(':');
+
+	let user = parts.next()?.parse().ok()?;
+	let page = parts.next()?.parse().ok()?;
+	let limit: usize = parts.next()?.parse().ok()?;
+
+	if limit == 0 || limit as u64 > MAX_LIMIT {
+		return None;
+	}
+
+	return Some((UserId(user), page, limit));
+}
+
+fn button_id(page: &HistoryPage, target: usize) -> String {
+	return format!("{}{}:{}:{}", BUTTON_PREFIX, page.user, target, page.limit);
+}
+
+pub fn build_history<'a>(e: &'a mut CreateEmbed, page: &HistoryPage) -> &'a mut CreateEmbed {
+	if page.matches.is_empty() {
+		e.description(format!("No recorded matches for {}.", page.user.mention()));
+	} else {
+		e.description(format!("Recent matches of {}", page.user.mention()));
+	}
+
+	for record in &page.matches {
+		let result = match record.won {
+			Some(true) => "Victory",
+			Some(false) => "Defeat",
+			None => "Unfinished",
+		};
+
+		e.field(
+			format!("{} - {}", metadata::hero_name(&record.hero), result),
+			format!(
+				"K/D/A {}/{}/{} | {}\nMatch ID: {}",
+				record.kills, record.deaths, record.assists, bot::format_clock(record.duration), record.match_id
+			),
+			false,
+		);
+	}
+
+	e.footer(|f| {
+		f.text(format!("Page {}", page.page + 1))
+	});
+
+	return e;
+}
+
+pub fn build_buttons<'a>(c: &'a mut CreateComponents, page: &HistoryPage) -> &'a mut CreateComponents {
+	return c.create_action_row(|row| {
+		row.create_button(|b| {
+			b.custom_id(button_id(page, page.page.saturating_sub(1)))
+				.label("Previous")
+				.style(ButtonStyle::Secondary)
+				.disabled(page.page == 0)
+		});
+		row.create_button(|b| {
+			b.custom_id(button_id(page, page.page + 1))
+				.label("Next")
+				.style(ButtonStyle::Secondary)
+				.disabled(!page.has_next)
+		})
+	});
+}
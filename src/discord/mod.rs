@@ -1,20 +1,33 @@
 use serenity::async_trait;
 use serenity::client::{Context, EventHandler};
 use serenity::model::application::command::{Command, CommandOptionType};
+use serenity::model::channel::AttachmentType;
 use serenity::model::application::interaction::{Interaction, MessageFlags};
-use serenity::model::application::interaction::InteractionResponseType::ChannelMessageWithSource;
+use serenity::model::application::interaction::InteractionResponseType::{ChannelMessageWithSource, UpdateMessage};
 use serenity::model::gateway::Ready;
-use serenity::model::id::ChannelId;
+use serenity::model::id::{ChannelId, UserId};
+use serenity::model::mention::Mentionable;
 use serenity::model::Permissions;
 use serenity::prelude::TypeMapKey;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
-use crate::bot::{BotRequest, SteamId};
+use crate::bot::{BotError, BotRequest, SteamId};
+use crate::bot::events::EventKind;
+use crate::bot::spectate::SpectateMode;
+use crate::bot::stats::Metric;
+use crate::gsi::config;
+
+mod history;
+mod stats;
+mod steam;
 
 pub struct Events;
 
 pub struct DiscordData {
 	pub bot_req_tx: mpsc::Sender<BotRequest>,
+	/// Where users' Dota clients should post their game state.
+	pub gsi_public_url: String,
+	pub gsi_secret: Option<String>,
 }
 
 pub struct DiscordKey;
@@ -36,10 +49,22 @@ impl EventHandler for Events {
 						.create_option(|option| {
 							option
 								.name("steamid")
-								.description("Your SteamID64, which can be found at steamid.io")
+								.description("Your Dota friend ID, SteamID (any format) or steamcommunity.com/profiles/ link")
 								.kind(CommandOptionType::String)
 								.required(false)
 						})
+						.create_option(|option| {
+							option
+								.name("rotate")
+								.description("Set this to true to revoke your current auth token and get a new one.")
+								.kind(CommandOptionType::Boolean)
+								.required(false)
+						})
+				})
+				.create_application_command(|command| {
+					command
+						.name("unregister")
+						.description("Delete your registration, revoking your auth tokens and removing all your tracks.")
 				})
 				.create_application_command(|command| {
 					command
@@ -55,22 +80,174 @@ impl EventHandler for Events {
 								.required(true)
 						})
 				})
+				.create_application_command(|command| {
+					command
+						.name("events")
+						.description("Choose which in-game events this server's bound channel announces.")
+						.default_member_permissions(Permissions::ADMINISTRATOR)
+						.dm_permission(false)
+						.create_option(|option| {
+							option
+								.name("event")
+								.description("The event to turn on or off.")
+								.kind(CommandOptionType::String)
+								.required(true)
+								.add_string_choice("All events", "all");
+							for (value, label, _) in EventKind::ALL {
+								option.add_string_choice(label, value);
+							}
+							option
+						})
+						.create_option(|option| {
+							option
+								.name("enabled")
+								.description("Whether to announce it.")
+								.kind(CommandOptionType::Boolean)
+								.required(true)
+						})
+				})
 				.create_application_command(|command| {
 					command
 						.name("track")
-						.description("Start tracking your Dota matches in this channel.")
+						.description("Start posting your Dota matches in this server's bound channel.")
 						.create_option(|option| {
 							option
 								.name("disable")
-								.description("Set this to true to STOP tracking in this channel.")
+								.description("Set this to true to STOP tracking in this server.")
 								.kind(CommandOptionType::Boolean)
 								.required(false)
 						})
 				})
+				.create_application_command(|command| {
+					command
+						.name("spectate")
+						.description("Choose what happens when you spectate or cast a match with tracked players in it.")
+						.create_option(|option| {
+							option
+								.name("mode")
+								.description("What your client should do with spectated matches.")
+								.kind(CommandOptionType::String)
+								.required(true);
+							for (value, label, _) in SpectateMode::ALL {
+								option.add_string_choice(label, value);
+							}
+							option
+						})
+				})
+				.create_application_command(|command| {
+					command
+						.name("history")
+						.description("Show recently tracked matches.")
+						.create_option(|option| {
+							option
+								.name("user")
+								.description("Whose matches to show. Defaults to you.")
+								.kind(CommandOptionType::User)
+								.required(false)
+						})
+						.create_option(|option| {
+							option
+								.name("limit")
+								.description("Matches per page.")
+								.kind(CommandOptionType::Integer)
+								.min_int_value(1)
+								.max_int_value(history::MAX_LIMIT)
+								.required(false)
+						})
+				})
+				.create_application_command(|command| {
+					command
+						.name("stats")
+						.description("Show stats from tracked matches.")
+						.create_option(|option| {
+							option
+								.name("user")
+								.description("Whose stats to show. Defaults to you.")
+								.kind(CommandOptionType::User)
+								.required(false)
+						})
+						.create_option(|option| {
+							option
+								.name("hero")
+								.description("Only count matches on this hero.")
+								.kind(CommandOptionType::String)
+								.required(false)
+						})
+						.create_option(|option| {
+							option
+								.name("period")
+								.description("Only count recent matches. Defaults to all time.")
+								.kind(CommandOptionType::String)
+								.required(false);
+							for (value, label, _) in stats::PERIODS {
+								option.add_string_choice(label, value);
+							}
+							option
+						})
+				})
+				.create_application_command(|command| {
+					command
+						.name("leaderboard")
+						.description("Rank this server's tracked users.")
+						.dm_permission(false)
+						.create_option(|option| {
+							option
+								.name("metric")
+								.description("What to rank by.")
+								.kind(CommandOptionType::String)
+								.required(true);
+							for (value, label, _) in Metric::ALL {
+								option.add_string_choice(label, value);
+							}
+							option
+						})
+						.create_option(|option| {
+							option
+								.name("period")
+								.description("Only count recent matches. Defaults to all time.")
+								.kind(CommandOptionType::String)
+								.required(false);
+							for (value, label, _) in stats::PERIODS {
+								option.add_string_choice(label, value);
+							}
+							option
+						})
+				})
 		}).await.unwrap();
 	}
 
 	async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+		if let Interaction::MessageComponent(component) = &interaction {
+			if let Some((user, page, limit)) = history::parse_button(&component.data.custom_id) {
+				log::trace!("Received history page {} request for {} from {}", page, user, component.user.id);
+
+				let data = ctx.data.read().await;
+				let data = data.get::<DiscordKey>().unwrap();
+
+				match history::fetch_page(&data.bot_req_tx, user, page, limit).await {
+					Ok(page) => {
+						component.create_interaction_response(&ctx, |f| {
+							f.kind(UpdateMessage);
+							f.interaction_response_data(|g| {
+								g.embed(|e| history::build_history(e, &page));
+								g.components(|c| history::build_buttons(c, &page))
+							})
+						}).await.unwrap();
+					}
+					Err(_) => {
+						component.create_interaction_response(&ctx, |f| {
+							f.kind(ChannelMessageWithSource);
+							f.interaction_response_data(|g| {
+								g.content("There was an unexpected error!");
+								g.flags(MessageFlags::EPHEMERAL)
+							})
+						}).await.unwrap();
+					}
+				}
+			}
+			return;
+		}
+
 		if let Interaction::ApplicationCommand(command) = interaction {
 			match command.guild_id {
 				None => {
@@ -82,55 +259,58 @@ impl EventHandler for Events {
 						})
 					}).await.unwrap();
 				}
-				Some(_gid) => {
+				Some(gid) => {
 					match command.data.name.as_str() {
 						"register" => {
-							log::trace!("Received track request from {} in channel {}", command.user.id, command.channel_id);
+							log::trace!("Received register request from {} in channel {}", command.user.id, command.channel_id);
 
-							let steam_id: Option<SteamId> = match command.data.options.get(0) {
-								None => None,
-								Some(x) => {
-									match &x.value {
-										None => None,
-										Some(x) => {
-											match x.as_str() {
-												None => None,
-												Some(x) => {
-													match x.parse() {
-														Ok(steam_id) => Some(steam_id),
-														Err(_) => None,
-													}
-												}
-											}
-										}
-									}
+							let rotate = command.data.options.iter()
+								.find(|o| o.name == "rotate")
+								.and_then(|o| o.value.as_ref())
+								.and_then(|v| v.as_bool())
+								.unwrap_or(false);
+
+							let (tx, rx) = oneshot::channel();
+
+							let request = if rotate {
+								log::trace!("Attempting to rotate the auth token of {}", command.user.id);
+
+								BotRequest::RotateToken {
+									user: command.user.id,
+									resp: tx,
 								}
-							};
+							} else {
+								let steam_id: Option<SteamId> = command.data.options.iter()
+									.find(|o| o.name == "steamid")
+									.and_then(|o| o.value.as_ref())
+									.and_then(|v| v.as_str())
+									.and_then(steam::parse_steam_id);
+
+								let steam_id = match steam_id {
+									None => {
+										command.create_interaction_response(&ctx, |f| {
+											f.kind(ChannelMessageWithSource);
+											f.interaction_response_data(|g| {
+												g.content("Invalid SteamID! Use your Dota friend ID, a SteamID like `STEAM_0:0:11101` or `[U:1:22202]`, a SteamID64, or your steamcommunity.com/profiles/ link.");
+												g.flags(MessageFlags::EPHEMERAL)
+											})
+										}).await.unwrap();
+										return;
+									}
+									Some(steam_id) => steam_id,
+								};
 
-							let steam_id = match steam_id {
-								None => {
-									command.create_interaction_response(&ctx, |f| {
-										f.kind(ChannelMessageWithSource);
-										f.interaction_response_data(|g| {
-											g.content("Invalid SteamID! Make sure to use the SteamID64.");
-											g.flags(MessageFlags::EPHEMERAL)
-										})
-									}).await.unwrap();
-									return;
+								log::trace!("Attempting to register {} with SteamID {}", command.user.id, steam_id);
+
+								BotRequest::RegisterUser {
+									user: command.user.id,
+									steam_id,
+									resp: tx,
 								}
-								Some(steam_id) => steam_id,
 							};
 
-							log::trace!("Attempting to register {} with SteamID {}", command.user.id, steam_id);
-
 							let data = ctx.data.read().await;
 							let data = data.get::<DiscordKey>().unwrap();
-							let (tx, rx) = oneshot::channel();
-							let request = BotRequest::RegisterUser {
-								user: command.user.id,
-								steam_id,
-								resp: tx,
-							};
 
 							log::trace!("Sending bot request");
 
@@ -142,25 +322,75 @@ impl EventHandler for Events {
 
 							match resp {
 								Ok(resp) => {
+									let cfg = config::render_config(&data.gsi_public_url, &resp, data.gsi_secret.as_deref());
+									let header = if rotate {
+										"Your old auth token has been revoked! Replace your old cfg file with this one."
+									} else {
+										"Registered!"
+									};
+
 									command.create_interaction_response(&ctx, |f| {
 										f.kind(ChannelMessageWithSource);
 										f.interaction_response_data(|g| {
-											g.content(format!("Auth token: {}", resp));
+											g.content(format!("{} Your auth token is `{}`.\n\n{}", header, resp, config::INSTALL_INSTRUCTIONS));
+											g.add_file(AttachmentType::Bytes {
+												data: cfg.into_bytes().into(),
+												filename: config::CONFIG_FILE_NAME.to_owned(),
+											});
 											g.flags(MessageFlags::EPHEMERAL)
 										})
 									}).await.unwrap();
 								}
-								Err(_) => {
+								Err(e) => {
+									let content = match e {
+										BotError::NotRegistered => "You are not registered yet! Use `/register` with your SteamID first.",
+										BotError::SteamIdTaken => "That Steam account is already registered by someone else!",
+										_ => "There was an unexpected error!",
+									};
+
 									command.create_interaction_response(&ctx, |f| {
 										f.kind(ChannelMessageWithSource);
 										f.interaction_response_data(|g| {
-											g.content("There was an unexpected error!");
+											g.content(content);
 											g.flags(MessageFlags::EPHEMERAL)
 										})
 									}).await.unwrap();
 								}
 							}
 						}
+						"unregister" => {
+							log::trace!("Received unregister request from {}", command.user.id);
+
+							let data = ctx.data.read().await;
+							let data = data.get::<DiscordKey>().unwrap();
+							let (tx, rx) = oneshot::channel();
+							let request = BotRequest::UnregisterUser {
+								user: command.user.id,
+								resp: tx,
+							};
+
+							log::trace!("Sending bot request");
+
+							data.bot_req_tx.send(request).await.unwrap();
+
+							let resp = rx.await.unwrap();
+
+							log::trace!("Received bot response");
+
+							let content = match resp {
+								Ok(()) => "You have been unregistered. Your auth tokens no longer work and your tracks have been removed.",
+								Err(BotError::NotRegistered) => "You are not registered!",
+								Err(_) => "There was an unexpected error!",
+							};
+
+							command.create_interaction_response(&ctx, |f| {
+								f.kind(ChannelMessageWithSource);
+								f.interaction_response_data(|g| {
+									g.content(content);
+									g.flags(MessageFlags::EPHEMERAL)
+								})
+							}).await.unwrap();
+						}
 						"bind" => {
 							log::trace!("Received bind request from {}", command.user.id);
 
@@ -183,12 +413,13 @@ impl EventHandler for Events {
 							let channel: u64 = channel.as_str().unwrap().parse().unwrap();
 							let channel = ChannelId::from(channel);
 
-							log::trace!("Attempting to bind to {}", channel);
+							log::trace!("Attempting to bind guild {} to {}", gid, channel);
 
 							let data = ctx.data.read().await;
 							let data = data.get::<DiscordKey>().unwrap();
 							let (tx, rx) = oneshot::channel();
 							let request = BotRequest::BindChannel {
+								guild: gid,
 								channel,
 								resp: tx,
 							};
@@ -201,28 +432,95 @@ impl EventHandler for Events {
 
 							log::trace!("Received bot response");
 
-							if resp.is_ok() {
+							let content = match resp {
+								Ok(()) => format!("Successfully bound to {}", channel.mention()),
+								Err(_) => format!("Error binding to {}!", channel.mention()),
+							};
+
+							command.create_interaction_response(&ctx, |f| {
+								f.kind(ChannelMessageWithSource);
+								f.interaction_response_data(|g| {
+									g.content(content);
+									g.flags(MessageFlags::EPHEMERAL)
+								})
+							}).await.unwrap();
+						}
+						"events" => {
+							log::trace!("Received events request from {}", command.user.id);
+
+							let member = command.member.as_ref().unwrap();
+							let perms = member.permissions.as_ref().unwrap();
+
+							if !perms.contains(Permissions::ADMINISTRATOR) {
 								command.create_interaction_response(&ctx, |f| {
 									f.kind(ChannelMessageWithSource);
 									f.interaction_response_data(|g| {
-										g.content(format!("Successfully bound to {}", channel));
+										g.content("Only server Administrators can change which events are announced!");
 										g.flags(MessageFlags::EPHEMERAL)
 									})
 								}).await.unwrap();
-							} else {
-								if resp.is_ok() {
-									command.create_interaction_response(&ctx, |f| {
-										f.kind(ChannelMessageWithSource);
-										f.interaction_response_data(|g| {
-											g.content(format!("Error binding to {}!", channel));
-											g.flags(MessageFlags::EPHEMERAL)
-										})
-									}).await.unwrap();
-								}
+
+								return;
 							}
+
+							// "all" isn't an event, so it maps to None.
+							let kind = command.data.options.iter()
+								.find(|o| o.name == "event")
+								.and_then(|o| o.value.as_ref())
+								.and_then(|v| v.as_str())
+								.and_then(EventKind::from_name);
+
+							let enabled = command.data.options.iter()
+								.find(|o| o.name == "enabled")
+								.and_then(|o| o.value.as_ref())
+								.and_then(|v| v.as_bool())
+								.unwrap_or(true);
+
+							let data = ctx.data.read().await;
+							let data = data.get::<DiscordKey>().unwrap();
+							let (tx, rx) = oneshot::channel();
+							let request = BotRequest::SetEvents {
+								guild: gid,
+								kind,
+								enabled,
+								resp: tx,
+							};
+
+							log::trace!("Sending bot request");
+
+							data.bot_req_tx.send(request).await.unwrap();
+
+							let resp = rx.await.unwrap();
+
+							log::trace!("Received bot response");
+
+							let content = match resp {
+								Ok(filter) => {
+									let announced = EventKind::ALL.iter()
+										.filter(|(_, _, kind)| filter.allows(*kind))
+										.map(|(_, label, _)| *label)
+										.collect::<Vec<_>>();
+
+									if announced.is_empty() {
+										"No events will be announced.".to_owned()
+									} else {
+										format!("Announcing: {}.", announced.join(", "))
+									}
+								}
+								Err(BotError::NotBound) => "This server has no bound channel yet! Run `/bind` first.".to_owned(),
+								Err(_) => "There was an unexpected error!".to_owned(),
+							};
+
+							command.create_interaction_response(&ctx, |f| {
+								f.kind(ChannelMessageWithSource);
+								f.interaction_response_data(|g| {
+									g.content(content);
+									g.flags(MessageFlags::EPHEMERAL)
+								})
+							}).await.unwrap();
 						}
 						"track" => {
-							log::trace!("Received track request from {} in channel {}", command.user.id, command.channel_id);
+							log::trace!("Received track request from {} in guild {}", command.user.id, gid);
 
 							let disable = match command.data.options.get(0) {
 								None => false,
@@ -241,25 +539,83 @@ impl EventHandler for Events {
 								}
 							};
 
-							log::trace!("Attempting to add track for {} in {}", command.user.id, command.channel_id);
-
 							let data = ctx.data.read().await;
 							let data = data.get::<DiscordKey>().unwrap();
-							let (tx, rx) = oneshot::channel();
-							let request = if disable {
-								BotRequest::RemoveTrack {
+
+							let content = if disable {
+								log::trace!("Attempting to remove track for {} in {}", command.user.id, gid);
+
+								let (tx, rx) = oneshot::channel();
+								let request = BotRequest::RemoveTrack {
 									user: command.user.id,
-									channel: command.channel_id,
+									guild: gid,
 									resp: tx,
+								};
+
+								log::trace!("Sending bot request");
+
+								data.bot_req_tx.send(request).await.unwrap();
+
+								let resp = rx.await.unwrap();
+
+								log::trace!("Received bot response");
+
+								match resp {
+									Ok(()) => "Your matches will no longer be posted in this server.".to_owned(),
+									Err(_) => "There was an unexpected error!".to_owned(),
 								}
 							} else {
-								BotRequest::AddTrack {
+								log::trace!("Attempting to add track for {} in {}", command.user.id, gid);
+
+								let (tx, rx) = oneshot::channel();
+								let request = BotRequest::AddTrack {
 									user: command.user.id,
-									channel: command.channel_id,
+									guild: gid,
 									resp: tx,
+								};
+
+								log::trace!("Sending bot request");
+
+								data.bot_req_tx.send(request).await.unwrap();
+
+								let resp = rx.await.unwrap();
+
+								log::trace!("Received bot response");
+
+								match resp {
+									Ok(channel) => format!("Success! Your matches will be posted in {}.", channel.mention()),
+									Err(BotError::NotBound) => "This server has no bound channel yet! Ask an Administrator to run `/bind` first.".to_owned(),
+									Err(_) => "There was an unexpected error!".to_owned(),
 								}
 							};
 
+							command.create_interaction_response(&ctx, |f| {
+								f.kind(ChannelMessageWithSource);
+								f.interaction_response_data(|g| {
+									g.content(content);
+									g.flags(MessageFlags::EPHEMERAL)
+								})
+							}).await.unwrap();
+						}
+						"spectate" => {
+							log::trace!("Received spectate request from {}", command.user.id);
+
+							let mode = command.data.options.iter()
+								.find(|o| o.name == "mode")
+								.and_then(|o| o.value.as_ref())
+								.and_then(|v| v.as_str())
+								.and_then(SpectateMode::from_name)
+								.unwrap_or_default();
+
+							let data = ctx.data.read().await;
+							let data = data.get::<DiscordKey>().unwrap();
+							let (tx, rx) = oneshot::channel();
+							let request = BotRequest::SetSpectate {
+								user: command.user.id,
+								mode,
+								resp: tx,
+							};
+
 							log::trace!("Sending bot request");
 
 							data.bot_req_tx.send(request).await.unwrap();
@@ -268,20 +624,178 @@ impl EventHandler for Events {
 
 							log::trace!("Received bot response");
 
-							if resp.is_ok() {
-								command.create_interaction_response(&ctx, |f| {
-									f.kind(ChannelMessageWithSource);
-									f.interaction_response_data(|g| {
-										g.content("Success!");
-										g.flags(MessageFlags::EPHEMERAL)
-									})
-								}).await.unwrap();
-							} else {
-								if resp.is_ok() {
+							let content = match (resp, mode) {
+								(Ok(()), SpectateMode::Off) => "Matches you spectate will be ignored.",
+								(Ok(()), SpectateMode::Players) => "Matches you spectate will now update the posts of tracked players in them.",
+								(Ok(()), SpectateMode::Scoreboard) => "Matches you spectate will now update the posts of tracked players in them, and get a scoreboard in the servers you track in.",
+								(Err(BotError::NotRegistered), _) => "You are not registered yet! Use `/register` with your SteamID first.",
+								(Err(_), _) => "There was an unexpected error!",
+							};
+
+							command.create_interaction_response(&ctx, |f| {
+								f.kind(ChannelMessageWithSource);
+								f.interaction_response_data(|g| {
+									g.content(content);
+									g.flags(MessageFlags::EPHEMERAL)
+								})
+							}).await.unwrap();
+						}
+						"history" => {
+							let user = command.data.options.iter()
+								.find(|o| o.name == "user")
+								.and_then(|o| o.value.as_ref())
+								.and_then(|v| v.as_str())
+								.and_then(|v| v.parse().ok())
+								.map(UserId)
+								.unwrap_or(command.user.id);
+
+							let limit = command.data.options.iter()
+								.find(|o| o.name == "limit")
+								.and_then(|o| o.value.as_ref())
+								.and_then(|v| v.as_u64())
+								.unwrap_or(history::DEFAULT_LIMIT)
+								.clamp(1, history::MAX_LIMIT);
+
+							log::trace!("Received history request for {} from {}", user, command.user.id);
+
+							let data = ctx.data.read().await;
+							let data = data.get::<DiscordKey>().unwrap();
+
+							match history::fetch_page(&data.bot_req_tx, user, 0, limit as usize).await {
+								Ok(page) => {
+									command.create_interaction_response(&ctx, |f| {
+										f.kind(ChannelMessageWithSource);
+										f.interaction_response_data(|g| {
+											g.embed(|e| history::build_history(e, &page));
+											g.components(|c| history::build_buttons(c, &page))
+										})
+									}).await.unwrap();
+								}
+								Err(_) => {
+									command.create_interaction_response(&ctx, |f| {
+										f.kind(ChannelMessageWithSource);
+										f.interaction_response_data(|g| {
+											g.content("There was an unexpected error!");
+											g.flags(MessageFlags::EPHEMERAL)
+										})
+									}).await.unwrap();
+								}
+							}
+						}
+						"stats" => {
+							let user = command.data.options.iter()
+								.find(|o| o.name == "user")
+								.and_then(|o| o.value.as_ref())
+								.and_then(|v| v.as_str())
+								.and_then(|v| v.parse().ok())
+								.map(UserId)
+								.unwrap_or(command.user.id);
+
+							let hero = command.data.options.iter()
+								.find(|o| o.name == "hero")
+								.and_then(|o| o.value.as_ref())
+								.and_then(|v| v.as_str())
+								.map(str::to_owned);
+
+							let period = command.data.options.iter()
+								.find(|o| o.name == "period")
+								.and_then(|o| o.value.as_ref())
+								.and_then(|v| v.as_str());
+							let (period, since) = stats::parse_period(period);
+
+							log::trace!("Received stats request for {} from {}", user, command.user.id);
+
+							let data = ctx.data.read().await;
+							let data = data.get::<DiscordKey>().unwrap();
+							let (tx, rx) = oneshot::channel();
+							let request = BotRequest::MatchStats {
+								user,
+								hero: hero.clone(),
+								since,
+								resp: tx,
+							};
+
+							log::trace!("Sending bot request");
+
+							data.bot_req_tx.send(request).await.unwrap();
+
+							let resp = rx.await.unwrap();
+
+							log::trace!("Received bot response");
+
+							match resp {
+								Ok(resp) => {
+									command.create_interaction_response(&ctx, |f| {
+										f.kind(ChannelMessageWithSource);
+										f.interaction_response_data(|g| {
+											g.embed(|e| stats::build_stats(e, user, hero.as_deref(), period, &resp))
+										})
+									}).await.unwrap();
+								}
+								Err(_) => {
+									command.create_interaction_response(&ctx, |f| {
+										f.kind(ChannelMessageWithSource);
+										f.interaction_response_data(|g| {
+											g.content("There was an unexpected error!");
+											g.flags(MessageFlags::EPHEMERAL)
+										})
+									}).await.unwrap();
+								}
+							}
+						}
+						"leaderboard" => {
+							let metric = command.data.options.iter()
+								.find(|o| o.name == "metric")
+								.and_then(|o| o.value.as_ref())
+								.and_then(|v| v.as_str())
+								.and_then(Metric::from_name)
+								.unwrap_or(Metric::Wins);
+
+							let period = command.data.options.iter()
+								.find(|o| o.name == "period")
+								.and_then(|o| o.value.as_ref())
+								.and_then(|v| v.as_str());
+							let (period, since) = stats::parse_period(period);
+
+							log::trace!("Received leaderboard request for {:?} in guild {}", metric, gid);
+
+							let data = ctx.data.read().await;
+							let data = data.get::<DiscordKey>().unwrap();
+							let (tx, rx) = oneshot::channel();
+							let request = BotRequest::Leaderboard {
+								guild: gid,
+								metric,
+								since,
+								resp: tx,
+							};
+
+							log::trace!("Sending bot request");
+
+							data.bot_req_tx.send(request).await.unwrap();
+
+							let resp = rx.await.unwrap();
+
+							log::trace!("Received bot response");
+
+							match resp {
+								Ok(resp) => {
+									command.create_interaction_response(&ctx, |f| {
+										f.kind(ChannelMessageWithSource);
+										f.interaction_response_data(|g| {
+											g.embed(|e| stats::build_leaderboard(e, metric, period, &resp))
+										})
+									}).await.unwrap();
+								}
+								Err(e) => {
+									let content = match e {
+										BotError::NotBound => "This server has no bound channel yet! Ask an Administrator to run `/bind` first.",
+										_ => "There was an unexpected error!",
+									};
+
 									command.create_interaction_response(&ctx, |f| {
 										f.kind(ChannelMessageWithSource);
 										f.interaction_response_data(|g| {
-											g.content("The bot is not bound to this channel! Please switch to a valid channel.");
+											g.content(content);
 											g.flags(MessageFlags::EPHEMERAL)
 										})
 									}).await.unwrap();
@@ -0,0 +1,106 @@
+use chrono::Utc;
+use serenity::builder::CreateEmbed;
+use serenity::model::id::UserId;
+use serenity::model::mention::Mentionable;
+use crate::bot::stats::{Metric, Stats, MIN_WIN_RATE_GAMES};
+use crate::metadata;
+
+/// The time windows offered by `/stats` and `/leaderboard`, as (choice value, label, days).
+pub const PERIODS: &[(&str, &str, Option<i64>)] = &[
+	("week", "Last 7 days", Some(7)),
+	("month", "Last 30 days", Some(30)),
+	("all", "All time", None),
+];
+
+/// Looks up a period choice, falling back to all time. Returns its label and the earliest timestamp it covers.
+pub fn parse_period(value: Option<&str>) -> (&'static str, Option<i64>) {
+	let (_, label, days) = PERIODS.iter()
+		.find(|(v, _, _)| Some(*v) == value)
+		.unwrap_or(&PERIODS[PERIODS.len() - 1]);
+
+	return (label, days.map(|days| Utc::now().timestamp() - days * 24 * 60 * 60));
+}
+
+pub fn build_stats<'a>(e: &'a mut CreateEmbed, user: UserId, hero: Option<&str>, period: &str, stats: &Stats) -> &'a mut CreateEmbed {
+	match hero {
+		Some(hero) => match metadata::find_hero(hero) {
+			Some(info) => e.description(format!("Stats of {} on {} ({})", user.mention(), info.localized_name, period))
+				.thumbnail(info.portrait_url()),
+			None => e.description(format!("Stats of {} on {} ({})", user.mention(), hero, period)),
+		},
+		None => e.description(format!("Stats of {} ({})", user.mention(), period)),
+	};
+
+	if stats.games == 0 {
+		e.field("Matches", "No recorded matches.", false);
+		return e;
+	}
+
+	e.field("Matches", stats.games, true);
+
+	let win_rate = match stats.win_rate() {
+		Some(rate) => format!("{:.1}% ({}W/{}L)", rate * 100.0, stats.wins, stats.losses),
+		None => "-".to_owned(),
+	};
+	e.field("Win Rate", win_rate, true);
+
+	let streak = match stats.streak {
+		0 => "-".to_owned(),
+		s if s > 0 => format!("{} win{}", s, if s == 1 { "" } else { "s" }),
+		s => format!("{} loss{}", -s, if s == -1 { "" } else { "es" }),
+	};
+	e.field("Streak", streak, true);
+
+	e.field("Avg. K/D/A", format!(
+		"{:.1}/{:.1}/{:.1} ({:.2})",
+		stats.avg_kills, stats.avg_deaths, stats.avg_assists, stats.kda_ratio()
+	), true);
+
+	e.field("Avg. XPM/GPM", format!("{:.0}/{:.0}", stats.avg_xpm, stats.avg_gpm), true);
+
+	if hero.is_none() {
+		let heroes = stats.top_heroes.iter()
+			.map(|h| format!("{} - {} match{}, {}W", metadata::hero_name(&h.hero), h.games, if h.games == 1 { "" } else { "es" }, h.wins))
+			.collect::<Vec<_>>()
+			.join("\n");
+		e.field("Most Played", heroes, false);
+	}
+
+	e.timestamp(Utc::now());
+
+	return e;
+}
+
+pub fn build_leaderboard<'a>(e: &'a mut CreateEmbed, metric: Metric, period: &str, ranked: &[(UserId, Stats)]) -> &'a mut CreateEmbed {
+	e.title(format!("Leaderboard: {} ({})", metric.label(), period));
+
+	if ranked.is_empty() {
+		e.description("Nobody in this server has qualifying matches yet.");
+	} else {
+		let lines = ranked.iter()
+			.enumerate()
+			.map(|(i, (user, stats))| {
+				let value = match metric {
+					Metric::Wins => format!("{} wins", stats.wins),
+					Metric::WinRate => format!("{:.1}% ({}W/{}L)", stats.win_rate().unwrap_or_default() * 100.0, stats.wins, stats.losses),
+					Metric::AvgGpm => format!("{:.0} GPM", stats.avg_gpm),
+					Metric::Kills => format!("{} kills", stats.kills),
+					Metric::Games => format!("{} matches", stats.games),
+				};
+				format!("**{}.** {} - {}", i + 1, user.mention(), value)
+			})
+			.collect::<Vec<_>>()
+			.join("\n");
+		e.description(lines);
+	}
+
+	if metric == Metric::WinRate {
+		e.footer(|f| {
+			f.text(format!("Needs at least {} decided matches.", MIN_WIN_RATE_GAMES))
+		});
+	}
+
+	e.timestamp(Utc::now());
+
+	return e;
+}
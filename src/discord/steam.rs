@@ -0,0 +1,141 @@
+use crate::bot::SteamId;
+
+/// SteamID64 of account 0 in the public universe, for individual accounts on the desktop instance.
+const ID64_BASE: SteamId = 76561197960265728;
+
+/// Parses any of the common ways people write down a Steam account:
+///
+/// - SteamID64, e.g. `76561197960287930`
+/// - Steam32 account ID (the Dota "friend ID"), e.g. `22202`
+/// - `STEAM_0:0:11101`
+/// - `[U:1:22202]`
+/// - `https://steamcommunity.com/profiles/76561197960287930/`
+///
+/// Only IDs that resolve to a real individual account are accepted.
+pub fn parse_steam_id(input: &str) -> Option<SteamId> {
+	let input = input.trim();
+
+	let id = if let Some(rest) = input.strip_prefix("STEAM_") {
+		parse_steam2(rest)?
+	} else if input.starts_with('[') || input.starts_with("U:") {
+		parse_steam3(input)?
+	} else if input.contains("steamcommunity.com") {
+		parse_profile_url(input)?
+	} else {
+		let n: u64 = input.parse().ok()?;
+		if n <= u32::MAX as u64 {
+			ID64_BASE + n
+		} else {
+			n
+		}
+	};
+
+	if is_individual_id64(id) {
+		Some(id)
+	} else {
+		None
+	}
+}
+
+/// `X:Y:Z` after the `STEAM_` prefix, where the account ID is `Z * 2 + Y`.
+fn parse_steam2(s: &str) -> Option<SteamId> {
+	let mut parts = s.split(':');
+	let universe: u8 = parts.next()?.parse().ok()?;
+	let y: u64 = parts.next()?.parse().ok()?;
+	let z: u64 = parts.next()?.parse().ok()?;
+
+	// Old tools print universe 0 for public accounts, so treat it like 1.
+	if parts.next().is_some() || universe > 1 || y > 1 || z > u32::MAX as u64 / 2 {
+		return None;
+	}
+
+	Some(ID64_BASE + z * 2 + y)
+}
+
+/// `[U:1:N]`, with or without the brackets.
+fn parse_steam3(s: &str) -> Option<SteamId> {
+	let s = s.trim_start_matches('[').trim_end_matches(']');
+	let mut parts = s.split(':');
+
+	if parts.next()? != "U" || parts.next()? != "1" {
+		return None;
+	}
+
+	let account: u64 = parts.next()?.parse().ok()?;
+
+	if parts.next().is_some() || account > u32::MAX as u64 {
+		return None;
+	}
+
+	Some(ID64_BASE + account)
+}
+
+/// `steamcommunity.com/profiles/<id64>` links. Vanity `/id/<name>` links need the Steam Web API to resolve, so they are not supported.
+fn parse_profile_url(s: &str) -> Option<SteamId> {
+	let (_, rest) = s.split_once("/profiles/")?;
+	let id = rest.split(['/', '?', '#']).next()?;
+	id.parse().ok()
+}
+
+fn is_individual_id64(id: SteamId) -> bool {
+	let universe = id >> 56;
+	let account_type = (id >> 52) & 0xF;
+	let instance = (id >> 32) & 0xFFFFF;
+	let account = id & 0xFFFFFFFF;
+
+	universe == 1 && account_type == 1 && instance == 1 && account != 0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const GABEN: SteamId = 76561197960287930;
+
+	#[test]
+	fn parses_every_format() {
+		assert_eq!(parse_steam_id("76561197960287930"), Some(GABEN));
+		assert_eq!(parse_steam_id("22202"), Some(GABEN));
+		assert_eq!(parse_steam_id("STEAM_0:0:11101"), Some(GABEN));
+		assert_eq!(parse_steam_id("STEAM_1:0:11101"), Some(GABEN));
+		assert_eq!(parse_steam_id("[U:1:22202]"), Some(GABEN));
+		assert_eq!(parse_steam_id("U:1:22202"), Some(GABEN));
+		assert_eq!(parse_steam_id("https://steamcommunity.com/profiles/76561197960287930"), Some(GABEN));
+		assert_eq!(parse_steam_id("steamcommunity.com/profiles/76561197960287930/?tab=all#top"), Some(GABEN));
+		assert_eq!(parse_steam_id("  22202\n"), Some(GABEN));
+	}
+
+	#[test]
+	fn rejects_other_universes() {
+		assert_eq!(parse_steam_id("STEAM_2:0:11101"), None);
+		assert_eq!(parse_steam_id("[U:2:22202]"), None);
+		assert_eq!(parse_steam_id(&(GABEN + (1 << 56)).to_string()), None);
+	}
+
+	#[test]
+	fn rejects_other_account_types() {
+		// A group, and an individual ID64 with the type nibble changed.
+		assert_eq!(parse_steam_id("103582791429521408"), None);
+		assert_eq!(parse_steam_id("[G:1:4]"), None);
+		assert_eq!(parse_steam_id(&(GABEN + (1 << 52)).to_string()), None);
+	}
+
+	#[test]
+	fn rejects_account_zero() {
+		assert_eq!(parse_steam_id("0"), None);
+		assert_eq!(parse_steam_id("STEAM_0:0:0"), None);
+		assert_eq!(parse_steam_id("[U:1:0]"), None);
+		assert_eq!(parse_steam_id(&ID64_BASE.to_string()), None);
+	}
+
+	#[test]
+	fn rejects_malformed_input() {
+		assert_eq!(parse_steam_id("https://steamcommunity.com/profiles/76561197960287930abc"), None);
+		assert_eq!(parse_steam_id("https://steamcommunity.com/id/gabelogannewell"), None);
+		assert_eq!(parse_steam_id("STEAM_0:2:11101"), None);
+		assert_eq!(parse_steam_id("STEAM_0:0:11101:5"), None);
+		assert_eq!(parse_steam_id("[U:1:22202:1]"), None);
+		assert_eq!(parse_steam_id("gaben"), None);
+		assert_eq!(parse_steam_id(""), None);
+	}
+}
@@ -0,0 +1,258 @@
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rusty_ulid::Ulid;
+use serde_json::Value;
+use tokio::sync::watch;
+
+/// Failed attempts allowed from one address within `FAILURE_WINDOW` before it gets blocked.
+const MAX_FAILURES: u32 = 5;
+const FAILURE_WINDOW: Duration = Duration::from_secs(60);
+const BLOCK_DURATION: Duration = Duration::from_secs(300);
+
+/// Connections one address may hold open at once. A Dota client only needs one, and each may buffer a full body before
+/// its token can be checked.
+const MAX_CONNECTIONS_PER_IP: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AuthError {
+	MissingToken,
+	InvalidToken,
+	UnknownToken,
+	BadSecret,
+}
+
+impl AuthError {
+	pub fn reason(self) -> &'static str {
+		match self {
+			AuthError::MissingToken => "no auth token",
+			AuthError::InvalidToken => "malformed auth token",
+			AuthError::UnknownToken => "unknown auth token",
+			AuthError::BadSecret => "wrong or missing shared secret",
+		}
+	}
+}
+
+struct Failures {
+	count: u32,
+	window_start: Instant,
+	blocked_until: Option<Instant>,
+}
+
+/// Checks the `auth` block of incoming GSI posts before they are handed to the bot.
+///
+/// A post must carry a `token` that belongs to a registered user, and, if a shared secret is configured, a matching `secret`.
+/// Addresses that keep failing get blocked for a while, and each address may only hold a few connections at once.
+pub struct Authenticator {
+	secret: Option<String>,
+	tokens: watch::Receiver<HashSet<Ulid>>,
+	failures: Mutex<HashMap<IpAddr, Failures>>,
+	connections: Mutex<HashMap<IpAddr, usize>>,
+}
+
+/// Holds one of an address's connection slots until dropped.
+pub struct ConnectionSlot<'a> {
+	auth: &'a Authenticator,
+	ip: IpAddr,
+}
+
+impl Drop for ConnectionSlot<'_> {
+	fn drop(&mut self) {
+		let mut connections = self.auth.connections.lock().unwrap();
+		if let Some(open) = connections.get_mut(&self.ip) {
+			*open -= 1;
+			if *open == 0 {
+				connections.remove(&self.ip);
+			}
+		}
+	}
+}
+
+impl Authenticator {
+	pub fn new(secret: Option<String>, tokens: watch::Receiver<HashSet<Ulid>>) -> Self {
+		Authenticator {
+			secret,
+			tokens,
+			failures: Mutex::new(HashMap::new()),
+			connections: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Takes a connection slot for the address, or `None` if it already has too many connections open.
+	pub fn open_connection(&self, ip: IpAddr) -> Option<ConnectionSlot<'_>> {
+		let mut connections = self.connections.lock().unwrap();
+		let open = connections.entry(ip).or_default();
+
+		if *open >= MAX_CONNECTIONS_PER_IP {
+			return None;
+		}
+
+		*open += 1;
+		Some(ConnectionSlot { auth: self, ip })
+	}
+
+	pub fn check(&self, body: &Value) -> Result<(), AuthError> {
+		let auth = body.get("auth");
+
+		if let Some(secret) = &self.secret {
+			match auth.and_then(|a| a.get("secret")).and_then(Value::as_str) {
+				Some(given) if given == secret => (),
+				_ => return Err(AuthError::BadSecret),
+			}
+		}
+
+		let token = match auth.and_then(|a| a.get("token")).and_then(Value::as_str) {
+			None => return Err(AuthError::MissingToken),
+			Some(token) => Ulid::from_str(token).map_err(|_| AuthError::InvalidToken)?,
+		};
+
+		if self.tokens.borrow().contains(&token) {
+			Ok(())
+		} else {
+			Err(AuthError::UnknownToken)
+		}
+	}
+
+	pub fn is_blocked(&self, ip: IpAddr) -> bool {
+		let failures = self.failures.lock().unwrap();
+		match failures.get(&ip).and_then(|f| f.blocked_until) {
+			Some(until) => until > Instant::now(),
+			None => false,
+		}
+	}
+
+	/// Records a failed attempt, returning `true` if this pushed the address over the limit.
+	pub fn record_failure(&self, ip: IpAddr) -> bool {
+		let now = Instant::now();
+		let mut failures = self.failures.lock().unwrap();
+
+		// Forget anyone who has been quiet for a while so the map can't grow without bound.
+		failures.retain(|_, f| match f.blocked_until {
+			Some(until) => until > now,
+			None => now.duration_since(f.window_start) < FAILURE_WINDOW,
+		});
+
+		let entry = failures.entry(ip).or_insert(Failures {
+			count: 0,
+			window_start: now,
+			blocked_until: None,
+		});
+
+		entry.count += 1;
+
+		if entry.count >= MAX_FAILURES && entry.blocked_until.is_none() {
+			entry.blocked_until = Some(now + BLOCK_DURATION);
+			true
+		} else {
+			false
+		}
+	}
+
+	pub fn record_success(&self, ip: IpAddr) {
+		self.failures.lock().unwrap().remove(&ip);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::*;
+
+	const TOKEN: &str = "01ARZ3NDEKTSV4RRFFQ69G5FAV";
+	const OTHER_TOKEN: &str = "01BX5ZZKBKACTAV9WEVGEMMVRZ";
+
+	fn authenticator(secret: Option<&str>) -> Authenticator {
+		let tokens = [Ulid::from_str(TOKEN).unwrap()].into_iter().collect();
+		let (_tx, rx) = watch::channel(tokens);
+		Authenticator::new(secret.map(str::to_owned), rx)
+	}
+
+	fn ip(last: u8) -> IpAddr {
+		IpAddr::from([192, 0, 2, last])
+	}
+
+	#[test]
+	fn accepts_registered_token() {
+		let auth = authenticator(None);
+		assert_eq!(auth.check(&json!({ "auth": { "token": TOKEN } })), Ok(()));
+	}
+
+	#[test]
+	fn rejects_missing_token() {
+		let auth = authenticator(None);
+		assert_eq!(auth.check(&json!({})), Err(AuthError::MissingToken));
+		assert_eq!(auth.check(&json!({ "auth": {} })), Err(AuthError::MissingToken));
+		assert_eq!(auth.check(&json!({ "auth": { "token": 5 } })), Err(AuthError::MissingToken));
+	}
+
+	#[test]
+	fn rejects_malformed_token() {
+		let auth = authenticator(None);
+		assert_eq!(auth.check(&json!({ "auth": { "token": "not a ulid" } })), Err(AuthError::InvalidToken));
+	}
+
+	#[test]
+	fn rejects_unknown_token() {
+		let auth = authenticator(None);
+		assert_eq!(auth.check(&json!({ "auth": { "token": OTHER_TOKEN } })), Err(AuthError::UnknownToken));
+	}
+
+	#[test]
+	fn checks_secret_when_configured() {
+		let auth = authenticator(Some("hunter2"));
+		assert_eq!(auth.check(&json!({ "auth": { "token": TOKEN, "secret": "hunter2" } })), Ok(()));
+		assert_eq!(auth.check(&json!({ "auth": { "token": TOKEN, "secret": "hunter3" } })), Err(AuthError::BadSecret));
+		assert_eq!(auth.check(&json!({ "auth": { "token": TOKEN } })), Err(AuthError::BadSecret));
+	}
+
+	#[test]
+	fn blocks_after_max_failures() {
+		let auth = authenticator(None);
+
+		for _ in 1..MAX_FAILURES {
+			assert!(!auth.record_failure(ip(1)));
+		}
+		assert!(!auth.is_blocked(ip(1)));
+
+		assert!(auth.record_failure(ip(1)));
+		assert!(auth.is_blocked(ip(1)));
+		assert!(!auth.is_blocked(ip(2)));
+
+		// Only the failure that crossed the limit reports it.
+		assert!(!auth.record_failure(ip(1)));
+	}
+
+	#[test]
+	fn success_resets_failures() {
+		let auth = authenticator(None);
+
+		for _ in 1..MAX_FAILURES {
+			auth.record_failure(ip(1));
+		}
+		auth.record_success(ip(1));
+
+		for _ in 1..MAX_FAILURES {
+			assert!(!auth.record_failure(ip(1)));
+		}
+		assert!(!auth.is_blocked(ip(1)));
+	}
+
+	#[test]
+	fn limits_connections_per_address() {
+		let auth = authenticator(None);
+
+		let slots = (0..MAX_CONNECTIONS_PER_IP)
+			.map(|_| auth.open_connection(ip(1)).unwrap())
+			.collect::<Vec<_>>();
+		assert!(auth.open_connection(ip(1)).is_none());
+		assert!(auth.open_connection(ip(2)).is_some());
+
+		drop(slots);
+		assert!(auth.open_connection(ip(1)).is_some());
+		assert!(auth.connections.lock().unwrap().is_empty());
+	}
+}
@@ -0,0 +1,49 @@
+use rusty_ulid::Ulid;
+
+/// Dota only picks up files in its `gamestate_integration` folder named `gamestate_integration_*.cfg`.
+pub const CONFIG_FILE_NAME: &str = "gamestate_integration_stalker.cfg";
+
+/// The data blocks the bot reads from each post. Anything else just bloats the payload.
+const DATA_BLOCKS: &[&str] = &["provider", "map", "player", "hero", "abilities", "items"];
+
+pub const INSTALL_INSTRUCTIONS: &str = "\
+1. Save the attached file into `<Steam library>/steamapps/common/dota 2 beta/game/dota/cfg/gamestate_integration/` (create the folder if it doesn't exist).
+2. In Steam, open Dota 2's Properties and add `-gamestateintegration` to its launch options.
+3. Restart Dota 2. Keep this file to yourself, it identifies you to the bot!";
+
+/// Renders a complete GSI config that makes the Dota client post to `uri` with the given credentials.
+/// The shared secret, if any, goes into every user's file, so it is only as private as the cfg files are.
+pub fn render_config(uri: &str, token: &Ulid, secret: Option<&str>) -> String {
+	let data = DATA_BLOCKS.iter()
+		.map(|block| format!("\t\t\"{}\"\t\"1\"\n", block))
+		.collect::<String>();
+
+	let secret = match secret {
+		None => String::new(),
+		Some(secret) => format!("\t\t\"secret\"\t\"{}\"\n", secret),
+	};
+
+	format!(
+		"\"Dota Stalker\"\n\
+		{{\n\
+		\t\"uri\"\t\t\"{uri}\"\n\
+		\t\"timeout\"\t\"5.0\"\n\
+		\t\"buffer\"\t\"0.1\"\n\
+		\t\"throttle\"\t\"0.5\"\n\
+		\t\"heartbeat\"\t\"30.0\"\n\
+		\t\"data\"\n\
+		\t{{\n\
+		{data}\
+		\t}}\n\
+		\t\"auth\"\n\
+		\t{{\n\
+		\t\t\"token\"\t\"{token}\"\n\
+		{secret}\
+		\t}}\n\
+		}}\n",
+		uri = uri,
+		data = data,
+		token = token,
+		secret = secret,
+	)
+}
@@ -0,0 +1,411 @@
+use std::fmt;
+
+use bytes::{Buf, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest request head (request line + headers) we are willing to buffer.
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+const MAX_HEADERS: usize = 32;
+
+pub struct Request {
+	pub method: String,
+	/// Minor HTTP version, i.e. 0 for HTTP/1.0 and 1 for HTTP/1.1.
+	pub version: u8,
+	pub path: String,
+	pub headers: Vec<(String, String)>,
+	pub body: BytesMut,
+}
+
+impl Request {
+	/// Looks up a header by name, ignoring case.
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers.iter()
+			.find(|(k, _)| k.eq_ignore_ascii_case(name))
+			.map(|(_, v)| v.as_str())
+	}
+
+	/// Whether the client wants the connection kept open after this request.
+	pub fn keep_alive(&self) -> bool {
+		match self.header("connection") {
+			Some(conn) if conn.eq_ignore_ascii_case("close") => false,
+			Some(conn) if conn.eq_ignore_ascii_case("keep-alive") => true,
+			_ => self.version >= 1,
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum HttpError {
+	Io(std::io::Error),
+	/// The peer closed the connection partway through a request.
+	Incomplete,
+	Malformed(&'static str),
+	TooLarge,
+}
+
+impl fmt::Display for HttpError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			HttpError::Io(e) => write!(f, "I/O error: {}", e),
+			HttpError::Incomplete => write!(f, "connection closed mid-request"),
+			HttpError::Malformed(why) => write!(f, "malformed request: {}", why),
+			HttpError::TooLarge => write!(f, "request too large"),
+		}
+	}
+}
+
+impl From<std::io::Error> for HttpError {
+	fn from(e: std::io::Error) -> Self {
+		HttpError::Io(e)
+	}
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Status {
+	Ok,
+	BadRequest,
+	Unauthorized,
+	NotFound,
+	PayloadTooLarge,
+	TooManyRequests,
+}
+
+impl Status {
+	fn line(self) -> &'static str {
+		match self {
+			Status::Ok => "200 OK",
+			Status::BadRequest => "400 Bad Request",
+			Status::Unauthorized => "401 Unauthorized",
+			Status::NotFound => "404 Not Found",
+			Status::PayloadTooLarge => "413 Payload Too Large",
+			Status::TooManyRequests => "429 Too Many Requests",
+		}
+	}
+}
+
+/// Reads one full HTTP/1.1 request from `stream`, honoring `Content-Length` and chunked transfer-encoding.
+///
+/// `buf` may already hold bytes left over from a previous read; anything read past the end of this request is left in it.
+/// Returns `Ok(None)` if the peer closed the connection cleanly before sending anything.
+pub async fn read_request<S>(stream: &mut S, buf: &mut BytesMut, max_body: usize) -> Result<Option<Request>, HttpError>
+	where S: AsyncRead + Unpin
+{
+	let (method, version, path, headers, head_len) = loop {
+		let mut raw_headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+		let mut req = httparse::Request::new(&mut raw_headers);
+
+		match req.parse(buf) {
+			Ok(httparse::Status::Complete(amt)) => {
+				let headers = req.headers.iter()
+					.map(|h| (h.name.to_owned(), String::from_utf8_lossy(h.value).into_owned()))
+					.collect::<Vec<_>>();
+
+				break (
+					req.method.unwrap_or_default().to_owned(),
+					req.version.unwrap_or_default(),
+					req.path.unwrap_or_default().to_owned(),
+					headers,
+					amt,
+				);
+			}
+			Ok(httparse::Status::Partial) => {
+				if buf.len() > MAX_HEAD_SIZE {
+					return Err(HttpError::TooLarge);
+				}
+			}
+			Err(_) => return Err(HttpError::Malformed("invalid request head")),
+		}
+
+		if stream.read_buf(buf).await? == 0 {
+			return if buf.is_empty() {
+				Ok(None)
+			} else {
+				Err(HttpError::Incomplete)
+			};
+		}
+	};
+
+	buf.advance(head_len);
+
+	let mut request = Request {
+		method,
+		version,
+		path,
+		headers,
+		body: BytesMut::new(),
+	};
+
+	let chunked = match request.header("transfer-encoding") {
+		None => false,
+		Some(te) if te.trim().eq_ignore_ascii_case("chunked") => true,
+		Some(_) => return Err(HttpError::Malformed("unsupported transfer-encoding")),
+	};
+
+	if chunked {
+		let mut decoder = ChunkedDecoder::default();
+		request.body = loop {
+			if let Some(body) = decoder.decode(buf, max_body)? {
+				break body;
+			}
+
+			if stream.read_buf(buf).await? == 0 {
+				return Err(HttpError::Incomplete);
+			}
+		};
+	} else {
+		let len = match request.header("content-length") {
+			None => 0,
+			Some(len) => len.trim().parse::<usize>()
+				.map_err(|_| HttpError::Malformed("invalid content-length"))?,
+		};
+
+		if len > max_body {
+			return Err(HttpError::TooLarge);
+		}
+
+		while buf.len() < len {
+			buf.reserve(len - buf.len());
+			if stream.read_buf(buf).await? == 0 {
+				return Err(HttpError::Incomplete);
+			}
+		}
+
+		request.body = buf.split_to(len);
+	}
+
+	Ok(Some(request))
+}
+
+/// Decodes a chunked body as it arrives, taking whatever it has parsed off the front of the buffer so that no byte is
+/// looked at or copied twice, however small the reads.
+#[derive(Default)]
+struct ChunkedDecoder {
+	body: BytesMut,
+	state: ChunkState,
+	/// How much of the buffer has already been searched for the end of the current line.
+	scanned: usize,
+}
+
+#[derive(Default, Copy, Clone)]
+enum ChunkState {
+	#[default]
+	Size,
+	/// Bytes left of the current chunk.
+	Data(usize),
+	/// The CRLF after a chunk.
+	DataEnd,
+	/// Any trailer fields, up to the terminating empty line.
+	Trailers,
+}
+
+impl ChunkedDecoder {
+	/// Returns the body once it is complete, or `None` if more data is needed.
+	fn decode(&mut self, buf: &mut BytesMut, max_body: usize) -> Result<Option<BytesMut>, HttpError> {
+		loop {
+			match self.state {
+				ChunkState::Size => {
+					let line = match self.take_line(buf)? {
+						Some(line) => line,
+						None => return Ok(None),
+					};
+
+					let line = std::str::from_utf8(&line)
+						.map_err(|_| HttpError::Malformed("invalid chunk size"))?;
+					// Chunk extensions (";name=value") are allowed but meaningless to us.
+					let size = line.split(';').next().unwrap_or_default().trim();
+					let size = usize::from_str_radix(size, 16)
+						.map_err(|_| HttpError::Malformed("invalid chunk size"))?;
+
+					// `body` never exceeds `max_body`, and checking this way round can't overflow on a huge size.
+					if size > max_body - self.body.len() {
+						return Err(HttpError::TooLarge);
+					}
+
+					self.state = if size == 0 { ChunkState::Trailers } else { ChunkState::Data(size) };
+				}
+				ChunkState::Data(left) => {
+					if buf.is_empty() {
+						return Ok(None);
+					}
+
+					let n = left.min(buf.len());
+					self.body.extend_from_slice(&buf[..n]);
+					buf.advance(n);
+
+					self.state = if n == left { ChunkState::DataEnd } else { ChunkState::Data(left - n) };
+				}
+				ChunkState::DataEnd => {
+					if buf.len() < 2 {
+						return Ok(None);
+					}
+
+					if &buf[..2] != b"\r\n" {
+						return Err(HttpError::Malformed("missing CRLF after chunk"));
+					}
+
+					buf.advance(2);
+					self.state = ChunkState::Size;
+				}
+				ChunkState::Trailers => {
+					match self.take_line(buf)? {
+						Some(line) if line.is_empty() => return Ok(Some(std::mem::take(&mut self.body))),
+						Some(_) => {}
+						None => return Ok(None),
+					}
+				}
+			}
+		}
+	}
+
+	/// Takes a line off the front of `buf`, without its CRLF, or `None` if it hasn't all arrived yet.
+	fn take_line(&mut self, buf: &mut BytesMut) -> Result<Option<BytesMut>, HttpError> {
+		// Back up one in case the last search ended between the CR and the LF.
+		let from = self.scanned.saturating_sub(1);
+
+		match find_crlf(&buf[from..]) {
+			Some(n) => {
+				self.scanned = 0;
+				let line = buf.split_to(from + n);
+				buf.advance(2);
+				Ok(Some(line))
+			}
+			None if buf.len() > MAX_HEAD_SIZE => Err(HttpError::Malformed("chunk line too long")),
+			None => {
+				self.scanned = buf.len();
+				Ok(None)
+			}
+		}
+	}
+}
+
+fn find_crlf(buf: &[u8]) -> Option<usize> {
+	buf.windows(2).position(|w| w == b"\r\n")
+}
+
+pub async fn write_response<S>(stream: &mut S, status: Status, keep_alive: bool) -> std::io::Result<()>
+	where S: AsyncWrite + Unpin
+{
+	let response = format!(
+		"HTTP/1.1 {}\r\nContent-Type: text/html\r\nContent-Length: 0\r\nConnection: {}\r\n\r\n",
+		status.line(),
+		if keep_alive { "keep-alive" } else { "close" },
+	);
+
+	stream.write_all(response.as_bytes()).await?;
+	stream.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+	use std::pin::Pin;
+	use std::task::{Context, Poll};
+
+	use tokio::io::ReadBuf;
+
+	use super::*;
+
+	const MAX_BODY: usize = 64;
+
+	/// Hands out its data a few bytes per read, like a slow client.
+	struct Trickle {
+		data: Vec<u8>,
+		pos: usize,
+		step: usize,
+	}
+
+	impl AsyncRead for Trickle {
+		fn poll_read(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+			let end = (self.pos + self.step).min(self.data.len()).min(self.pos + buf.remaining());
+			buf.put_slice(&self.data[self.pos..end]);
+			self.pos = end;
+			Poll::Ready(Ok(()))
+		}
+	}
+
+	async fn read(raw: &[u8], step: usize) -> Result<Option<Request>, HttpError> {
+		let mut stream = Trickle { data: raw.to_vec(), pos: 0, step };
+		read_request(&mut stream, &mut BytesMut::new(), MAX_BODY).await
+	}
+
+	#[tokio::test]
+	async fn reads_content_length_body() {
+		let raw = b"POST /gsi HTTP/1.1\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello";
+		let req = read(raw, raw.len()).await.unwrap().unwrap();
+		assert_eq!(req.method, "POST");
+		assert_eq!(req.path, "/gsi");
+		assert_eq!(&req.body[..], b"hello");
+		assert!(!req.keep_alive());
+	}
+
+	#[tokio::test]
+	async fn reads_chunked_body() {
+		let raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nTrailer: x\r\n\r\n";
+		let req = read(raw, raw.len()).await.unwrap().unwrap();
+		assert_eq!(&req.body[..], b"hello world");
+		assert!(req.keep_alive());
+	}
+
+	#[tokio::test]
+	async fn reads_across_partial_reads() {
+		let raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n";
+		let req = read(raw, 1).await.unwrap().unwrap();
+		assert_eq!(&req.body[..], b"abc");
+
+		let raw = b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc";
+		let req = read(raw, 2).await.unwrap().unwrap();
+		assert_eq!(&req.body[..], b"abc");
+	}
+
+	#[test]
+	fn decodes_chunks_split_anywhere() {
+		let raw = b"4\r\nwiki\r\n5;x=y\r\npedia\r\n0\r\n\r\n";
+
+		for split in 1..raw.len() {
+			let mut decoder = ChunkedDecoder::default();
+			let mut buf = BytesMut::from(&raw[..split]);
+			assert!(decoder.decode(&mut buf, MAX_BODY).unwrap().is_none(), "split at {}", split);
+
+			buf.extend_from_slice(&raw[split..]);
+			let body = decoder.decode(&mut buf, MAX_BODY).unwrap().unwrap();
+			assert_eq!(&body[..], b"wikipedia", "split at {}", split);
+			assert!(buf.is_empty());
+		}
+	}
+
+	#[tokio::test]
+	async fn leaves_pipelined_requests_in_buffer() {
+		let raw = b"POST / HTTP/1.1\r\nContent-Length: 1\r\n\r\naPOST / HTTP/1.1\r\nContent-Length: 1\r\n\r\nb";
+		let mut stream = Trickle { data: raw.to_vec(), pos: 0, step: raw.len() };
+		let mut buf = BytesMut::new();
+
+		let first = read_request(&mut stream, &mut buf, MAX_BODY).await.unwrap().unwrap();
+		let second = read_request(&mut stream, &mut buf, MAX_BODY).await.unwrap().unwrap();
+		assert_eq!(&first.body[..], b"a");
+		assert_eq!(&second.body[..], b"b");
+		assert!(read_request(&mut stream, &mut buf, MAX_BODY).await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn rejects_oversized_bodies() {
+		let raw = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+		assert!(matches!(read(raw.as_bytes(), raw.len()).await, Err(HttpError::TooLarge)));
+
+		let raw = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n", MAX_BODY + 1);
+		assert!(matches!(read(raw.as_bytes(), raw.len()).await, Err(HttpError::TooLarge)));
+	}
+
+	#[tokio::test]
+	async fn rejects_overflowing_chunk_size() {
+		let raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\nffffffffffffffff\r\n";
+		assert!(matches!(read(raw, raw.len()).await, Err(HttpError::TooLarge)));
+	}
+
+	#[tokio::test]
+	async fn rejects_truncated_requests() {
+		let raw = b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc";
+		assert!(matches!(read(raw, raw.len()).await, Err(HttpError::Incomplete)));
+
+		let raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n";
+		assert!(matches!(read(raw, raw.len()).await, Err(HttpError::Malformed(_))));
+	}
+}
@@ -1,89 +1,291 @@
+mod auth;
+pub mod config;
+mod http;
+
+use std::collections::HashSet;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
 use bytes::BytesMut;
 use dota::components::GameState;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpListener;
-use tokio::sync::mpsc;
+use tokio::net::{TcpListener, TcpStream};
+use rusty_ulid::Ulid;
+use serde_json::Value;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::sync::{mpsc, watch};
+use tokio::time;
+
+use self::auth::Authenticator;
+use self::http::{HttpError, Status};
+
+/// Late-game payloads run to a few hundred KiB; anything far beyond that is not a GSI post.
+const MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
 
-const OK: &str = "HTTP/1.1 200 OK\ncontent-type: text/html\n";
+/// How long reading one request may take, counting from when the previous one was answered, so it covers both the idle
+/// wait on a kept-alive connection and a client that trickles its request in.
+/// Dota posts at least every heartbeat (30s by default), so this comfortably spans one.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Pending connections the kernel queues per listener, as tokio's own `bind` uses.
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Back-off after a failed `accept`, so that e.g. running out of file descriptors doesn't spin the listener.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
 
 pub struct Server {
-	uri: String,
+	addrs: Vec<String>,
+	shared: Arc<Shared>,
+}
+
+/// State shared by every listener and connection.
+struct Shared {
+	path_prefix: Option<String>,
+	auth: Authenticator,
 }
 
 impl Server {
-	pub fn new(uri: &str) -> Self {
+	/// Creates a server listening on every address in `addrs`.
+	///
+	/// If `path_prefix` is set, only requests whose path falls under it are accepted; everything else gets a 404.
+	/// Posts must authenticate with one of the registered `tokens`, plus `secret` if one is given.
+	pub fn new(addrs: Vec<String>, path_prefix: Option<&str>, secret: Option<String>, tokens: watch::Receiver<HashSet<Ulid>>) -> Self {
 		Server {
-			uri: uri.to_owned(),
+			addrs,
+			shared: Arc::new(Shared {
+				path_prefix: path_prefix.and_then(normalize_path),
+				auth: Authenticator::new(secret, tokens),
+			}),
 		}
 	}
 
-	pub async fn run(self, tx: mpsc::Sender<GameState>) {
-		log::info!("Listening on {}", self.uri);
-
-		let listener = TcpListener::bind(self.uri).await.unwrap(); // TODO: Handle.
-
-		loop {
-			let (mut socket, addr) = listener.accept().await.unwrap(); // TODO: Handle.
-			log::trace!("Accepted: {}", addr);
-			let txi = tx.clone();
-
-			let _ = tokio::spawn(async move {
-				log::trace!("Task spawned...");
-
-				if let Err(_e) = socket.readable().await {
-					log::error!("Socket not readable!");
-					return; // TODO: Handle error.
-				};
-
-				let mut buf = BytesMut::with_capacity(122880);
-
-				let n = match socket.read_buf(&mut buf).await {
-					Ok(n) if n == 0 => {
-						log::debug!("Socket closed!");
-						return;
-					}
-					Ok(n) => n,
-					Err(_e) => {
-						log::error!("Failed to read from socket!");
-						return; // TODO: Handle error.
-					}
-				};
-
-				log::trace!("Read: {}", n);
-
-				if let Err(_e) = socket.write_all(OK.as_bytes()).await {
-					log::error!("Failed to write to socket!");
-					return; // TODO: Handle error.
-				};
-
-				log::trace!("Raw request: {:?}", buf);
-				let amt = match parse_headers(&buf) {
-					Some(amt) => amt,
-					None => {
-						return; // TODO: Handle error, incomplete headers!
-					}
-				};
-
-				let _ = buf.split_to(amt);
-				log::trace!("Raw data: {:?}", buf);
-
-				let game_state: GameState = serde_json::from_slice(&buf).expect("Failed to parse JSON body!");
-
-				txi.send(game_state).await.unwrap();
-			})
-				.await.unwrap(); // TODO: Handle.
+	/// Binds every configured address, logging the ones that fail. Returns no listeners if none could be bound.
+	pub async fn bind(&self) -> Vec<TcpListener> {
+		let mut listeners = Vec::new();
+
+		for addr in &self.addrs {
+			let listener = match bind_addr(addr).await {
+				Ok(listener) => listener,
+				Err(e) => {
+					log::error!("Failed to bind GSI listener to {}! `{}`", addr, e);
+					continue;
+				}
+			};
+
+			match &self.shared.path_prefix {
+				None => log::info!("Listening on {}", addr),
+				Some(prefix) => log::info!("Listening on {} under {}", addr, prefix),
+			}
+
+			listeners.push(listener);
+		}
+
+		listeners
+	}
+
+	pub async fn run(self, listeners: Vec<TcpListener>, tx: mpsc::Sender<GameState>) {
+		let handles = listeners.into_iter()
+			.map(|listener| tokio::spawn(accept_loop(listener, self.shared.clone(), tx.clone())))
+			.collect::<Vec<_>>();
+
+		for handle in handles {
+			let _ = handle.await;
 		}
 	}
 }
 
-pub fn parse_headers(buf: &[u8]) -> Option<usize> {
-	let mut headers = [httparse::EMPTY_HEADER; 16];
-	let mut r = httparse::Request::new(&mut headers);
+/// Binds the first address `addr` resolves to that works.
+async fn bind_addr(addr: &str) -> io::Result<TcpListener> {
+	let mut last_err = None;
+
+	for addr in tokio::net::lookup_host(addr).await? {
+		match bind_listener(addr) {
+			Ok(listener) => return Ok(listener),
+			Err(e) => last_err = Some(e),
+		}
+	}
+
+	Err(last_err.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "address resolved to nothing")))
+}
+
+/// Like `TcpListener::bind`, except that IPv6 sockets only take IPv6. Otherwise `[::]` is dual-stack on most Linux hosts
+/// and can't be bound next to `0.0.0.0`.
+fn bind_listener(addr: SocketAddr) -> io::Result<TcpListener> {
+	let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+
+	if addr.is_ipv6() {
+		socket.set_only_v6(true)?;
+	}
+
+	// Lets a restarted bot bind again while old connections linger in TIME_WAIT, as tokio does.
+	#[cfg(unix)]
+	socket.set_reuse_address(true)?;
+
+	socket.set_nonblocking(true)?;
+	socket.bind(&addr.into())?;
+	socket.listen(LISTEN_BACKLOG)?;
+
+	TcpListener::from_std(socket.into())
+}
+
+async fn accept_loop(listener: TcpListener, shared: Arc<Shared>, tx: mpsc::Sender<GameState>) {
+	loop {
+		let (socket, addr) = match listener.accept().await {
+			Ok(x) => x,
+			Err(e) => {
+				log::error!("Failed to accept GSI connection! `{}`", e);
+				time::sleep(ACCEPT_BACKOFF).await;
+				continue;
+			}
+		};
+
+		log::trace!("Accepted: {}", addr);
+		let txi = tx.clone();
+		let shared = shared.clone();
+
+		// Each connection runs on its own task; a failure (or panic) in one never reaches the listener.
+		tokio::spawn(async move {
+			handle_connection(socket, addr, shared, txi).await;
+			log::trace!("Connection from {} finished", addr);
+		});
+	}
+}
+
+/// Turns a user-supplied path prefix like `gsi/` into `/gsi`, or `None` if it is empty.
+pub fn normalize_path(path: &str) -> Option<String> {
+	let path = path.trim_matches('/');
+
+	if path.is_empty() {
+		None
+	} else {
+		Some(format!("/{}", path))
+	}
+}
+
+/// Whether `path` (which may carry a query string) falls under `prefix`.
+fn path_matches(path: &str, prefix: &str) -> bool {
+	let path = path.split('?').next().unwrap_or_default();
+
+	match path.strip_prefix(prefix) {
+		Some(rest) => rest.is_empty() || rest.starts_with('/'),
+		None => false,
+	}
+}
+
+async fn handle_connection(mut socket: TcpStream, addr: SocketAddr, shared: Arc<Shared>, tx: mpsc::Sender<GameState>) {
+	if shared.auth.is_blocked(addr.ip()) {
+		log::debug!("Turning away blocked address {}", addr);
+		respond(&mut socket, Status::TooManyRequests, false).await;
+		return;
+	}
+
+	let _slot = match shared.auth.open_connection(addr.ip()) {
+		Some(slot) => slot,
+		None => {
+			log::debug!("Turning away {}, which has too many connections open", addr);
+			respond(&mut socket, Status::TooManyRequests, false).await;
+			return;
+		}
+	};
+
+	let mut buf = BytesMut::with_capacity(122880);
+
+	loop {
+		let request = match time::timeout(REQUEST_TIMEOUT, http::read_request(&mut socket, &mut buf, MAX_BODY_SIZE)).await {
+			Err(_) => {
+				log::debug!("Connection from {} timed out", addr);
+				return;
+			}
+			Ok(Ok(Some(request))) => request,
+			Ok(Ok(None)) => {
+				log::debug!("Socket closed!");
+				return;
+			}
+			Ok(Err(HttpError::TooLarge)) => {
+				log::warn!("Rejecting oversized GSI request from {}!", addr);
+				respond(&mut socket, Status::PayloadTooLarge, false).await;
+				return;
+			}
+			Ok(Err(HttpError::Malformed(why))) => {
+				log::warn!("Rejecting malformed GSI request from {}: {}", addr, why);
+				respond(&mut socket, Status::BadRequest, false).await;
+				return;
+			}
+			Ok(Err(e)) => {
+				log::error!("Failed to read from {}! `{}`", addr, e);
+				return;
+			}
+		};
 
-	let status = r.parse(buf).expect("Failed to parse HTTP request");
+		log::trace!("Read {} {} with a {} byte body", request.method, request.path, request.body.len());
 
-	match status {
-		httparse::Status::Complete(amt) => Some(amt),
-		httparse::Status::Partial => None,
+		let keep_alive = request.keep_alive();
+
+		if let Some(prefix) = &shared.path_prefix {
+			if !path_matches(&request.path, prefix) {
+				log::debug!("Ignoring request from {} for unrouted path {}", addr, request.path);
+				if !respond(&mut socket, Status::NotFound, keep_alive).await || !keep_alive {
+					return;
+				}
+				continue;
+			}
+		}
+
+		let body: Value = match serde_json::from_slice(&request.body) {
+			Ok(body) => body,
+			Err(e) => {
+				log::warn!("Failed to parse JSON body from {}! `{}`", addr, e);
+				if !respond(&mut socket, Status::BadRequest, keep_alive).await || !keep_alive {
+					return;
+				}
+				continue;
+			}
+		};
+
+		if let Err(e) = shared.auth.check(&body) {
+			log::warn!("Rejecting unauthenticated GSI post from {}: {}", addr, e.reason());
+			if shared.auth.record_failure(addr.ip()) {
+				log::warn!("Too many failed attempts from {}, blocking it for a while", addr.ip());
+			}
+			respond(&mut socket, Status::Unauthorized, false).await;
+			return;
+		}
+
+		shared.auth.record_success(addr.ip());
+
+		match serde_json::from_value::<GameState>(body) {
+			Ok(game_state) => {
+				if !respond(&mut socket, Status::Ok, keep_alive).await {
+					return;
+				}
+
+				if tx.send(game_state).await.is_err() {
+					log::error!("Bot handler is gone, dropping game state!");
+					return;
+				}
+			}
+			Err(e) => {
+				log::warn!("Failed to decode game state from {}! `{}`", addr, e);
+				if !respond(&mut socket, Status::BadRequest, keep_alive).await {
+					return;
+				}
+			}
+		}
+
+		if !keep_alive {
+			return;
+		}
+	}
+}
+
+/// Writes a response, returning whether the connection is still usable.
+async fn respond(socket: &mut TcpStream, status: Status, keep_alive: bool) -> bool {
+	match http::write_response(socket, status, keep_alive).await {
+		Ok(()) => true,
+		Err(e) => {
+			log::error!("Failed to write to socket! `{}`", e);
+			false
+		}
 	}
 }
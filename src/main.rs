@@ -1,24 +1,36 @@
 #[macro_use]
 extern crate log;
 
+use std::collections::HashSet;
 use std::env;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use fern::colors::{Color, ColoredLevelConfig};
-use serenity::Client;
+use serenity::{CacheAndHttp, Client};
 use serenity::prelude::GatewayIntents;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use crate::bot::Bot;
 use crate::discord::{DiscordData, DiscordKey};
+use crate::storage::{FileStorage, SqliteStorage, Storage, StorageError};
 
 mod discord;
 mod gsi;
 mod bot;
+mod metadata;
+mod storage;
 
-const GSI_URI: &str = "127.0.0.1:3682";
+/// Used when `GSI_LISTEN` is not set.
+const DEFAULT_GSI_LISTEN: &str = "127.0.0.1:3682";
+
+/// Default `STORAGE_PATH` for each `STORAGE_BACKEND`.
+const DEFAULT_FILE_PATH: &str = "stalker.dat";
+const DEFAULT_SQLITE_PATH: &str = "stalker.db";
 
 #[tokio::main]
 async fn main() {
-    // Load the .env file to populate BOT_TOKEN and APP_ID
+    // Load the .env file to populate BOT_TOKEN, APP_ID and the optional GSI_* settings
     dotenv::dotenv().ok();
 
     let token = env::var("BOT_TOKEN").expect("Expected a token in the environment");
@@ -30,7 +42,43 @@ async fn main() {
 
     setup_logger();
 
-    let gsi = gsi::Server::new(GSI_URI);
+    // GSI_LISTEN is a comma-separated list of addresses, e.g. "0.0.0.0:3682,[::]:3682"
+    let gsi_addrs = env::var("GSI_LISTEN").unwrap_or_else(|_| DEFAULT_GSI_LISTEN.to_owned());
+    let gsi_addrs: Vec<String> = gsi_addrs.split(',')
+        .map(|addr| addr.trim().to_owned())
+        .filter(|addr| !addr.is_empty())
+        .collect();
+
+    let gsi_path = env::var("GSI_PATH").ok();
+
+    // Optional shared secret every GSI post must carry in its auth block, alongside the user's token.
+    // It is not confidential: /register writes it into the cfg it sends to every user. It only keeps
+    // out posts from clients that were never handed a cfg, and the per-user token is what authenticates.
+    let gsi_secret = env::var("GSI_SECRET").ok().filter(|s| !s.is_empty());
+
+    let (tokens_tx, tokens_rx) = watch::channel(HashSet::new());
+
+    // The URL Dota clients should post to, written into the cfg files handed out by /register.
+    // Defaults to the first concrete listen address, which is only right if users can reach it as-is.
+    // Wildcards like 0.0.0.0 or [::] can't be posted to, so listening only on those requires setting it.
+    let gsi_public_url = match env::var("GSI_PUBLIC_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            let unspecified = |addr: &str| addr.parse::<SocketAddr>().is_ok_and(|addr| addr.ip().is_unspecified());
+            let addr = match gsi_addrs.iter().map(String::as_str).find(|addr| !unspecified(addr)) {
+                Some(addr) => addr,
+                None if gsi_addrs.is_empty() => DEFAULT_GSI_LISTEN,
+                None => {
+                    error!("GSI_PUBLIC_URL must be set when only listening on wildcard addresses, which clients can't post to.");
+                    std::process::exit(1);
+                }
+            };
+            let path = gsi_path.as_deref().and_then(gsi::normalize_path).unwrap_or_default();
+            format!("http://{}{}", addr, path)
+        }
+    };
+
+    let gsi = gsi::Server::new(gsi_addrs, gsi_path.as_deref(), gsi_secret.clone(), tokens_rx);
 
     let mut client = Client::builder(token, GatewayIntents::non_privileged())
         .event_handler(discord::Events)
@@ -38,23 +86,69 @@ async fn main() {
         .await
         .expect("Error creating client");
 
+    // `dota_stalker import [stalker.dat] [stalker.db]` copies the file backend's data into a SQLite database and exits
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("import") {
+        let from = args.get(1).map(String::as_str).unwrap_or(DEFAULT_FILE_PATH);
+        let to = args.get(2).map(String::as_str).unwrap_or(DEFAULT_SQLITE_PATH);
+
+        match import(Path::new(from), Path::new(to), &client.cache_and_http).await {
+            Ok(()) => info!("Imported {} into {}", from, to),
+            Err(e) => {
+                error!("Failed to import {} into {}: {}", from, to, e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let storage = match open_storage(&client.cache_and_http).await {
+        Ok(storage) => storage,
+        Err(e) => {
+            error!("Failed to load saved data: {}", e);
+            match e {
+                StorageError::File(_) => error!("Refusing to start so it isn't overwritten. Fix it or restore the .1 backup next to it first."),
+                _ => error!("Refusing to start. Fix the database or point STORAGE_PATH at a good copy first."),
+            }
+            std::process::exit(1);
+        }
+    };
+
     let (gsi_tx, gsi_rx) = mpsc::channel(10);
     let (bot_req_tx, bot_req_rx) = mpsc::channel(10);
 
-    let bot = Bot::new(client.cache_and_http.clone(), bot_req_rx, gsi_rx);
+    // Least number of seconds between two edits of the same live embed
+    let edit_interval = env::var("EDIT_INTERVAL").ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(bot::DEFAULT_EDIT_INTERVAL);
 
-    let disc_data = DiscordData { bot_req_tx };
+    let bot = match Bot::new(client.cache_and_http.clone(), storage, bot_req_rx, gsi_rx, tokens_tx, edit_interval) {
+        Ok(bot) => bot,
+        Err(e) => {
+            error!("Failed to read saved data: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let disc_data = DiscordData { bot_req_tx, gsi_public_url, gsi_secret };
 
     client.data.write().await.insert::<DiscordKey>(disc_data);
 
     info!("Initializing Dota Stalker...");
 
+    let listeners = gsi.bind().await;
+    if listeners.is_empty() {
+        error!("No GSI listeners could be started! Check GSI_LISTEN.");
+        std::process::exit(1);
+    }
+
     tokio::spawn(async move {
         bot.run().await;
     });
 
     tokio::spawn(async move {
-        gsi.run(gsi_tx).await;
+        gsi.run(listeners, gsi_tx).await;
     });
 
     if let Err(why) = client.start().await {
@@ -64,6 +158,39 @@ async fn main() {
     info!("Goodbye!");
 }
 
+/// Opens the backend named by `STORAGE_BACKEND` ("file", the default, or "sqlite") at `STORAGE_PATH`.
+async fn open_storage(cah: &CacheAndHttp) -> Result<Box<dyn Storage>, StorageError> {
+    let backend = env::var("STORAGE_BACKEND").unwrap_or_else(|_| "file".to_owned());
+    let path = env::var("STORAGE_PATH").ok().map(PathBuf::from);
+
+    match backend.as_str() {
+        "sqlite" => {
+            let path = path.unwrap_or_else(|| PathBuf::from(DEFAULT_SQLITE_PATH));
+            info!("Using SQLite storage at {}", path.display());
+            Ok(Box::new(SqliteStorage::open(&path)?))
+        }
+        other => {
+            if other != "file" {
+                warn!("Unknown STORAGE_BACKEND {:?}, falling back to file storage", other);
+            }
+            let path = path.unwrap_or_else(|| PathBuf::from(DEFAULT_FILE_PATH));
+            info!("Using file storage at {}", path.display());
+            Ok(Box::new(FileStorage::open(&path, cah).await?))
+        }
+    }
+}
+
+async fn import(from: &Path, to: &Path, cah: &CacheAndHttp) -> Result<(), StorageError> {
+    let snapshot = FileStorage::read_snapshot(from, cah).await?;
+
+    info!(
+        "Importing {} registrations, {} bindings, {} tracks and {} matches",
+        snapshot.users.len(), snapshot.guilds.len(), snapshot.tracks.len(), snapshot.matches.len()
+    );
+
+    SqliteStorage::open(to)?.import(snapshot)
+}
+
 fn setup_logger() {
     let colors_line = ColoredLevelConfig::new()
         .error(Color::BrightRed)
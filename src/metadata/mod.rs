@@ -0,0 +1,256 @@
+//! Display names, attributes and artwork for Dota's internal hero, item and ability names, from tables bundled with the binary.
+//!
+//! Names missing from the tables (e.g. heroes newer than the build) fall back to a title-cased internal name.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+/// Where the image paths in the tables are served from.
+const CDN: &str = "https://cdn.cloudflare.steamstatic.com";
+
+const HEROES: &str = include_str!("heroes.json");
+const ITEMS: &str = include_str!("items.json");
+const ABILITIES: &str = include_str!("abilities.json");
+
+/// Where ability icons missing from the table are, by internal name.
+const ABILITY_ICONS: &str = "/apps/dota2/images/dota_react/abilities/";
+
+const HERO_PREFIX: &str = "npc_dota_hero_";
+const ITEM_PREFIX: &str = "item_";
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Attribute {
+	Str,
+	Agi,
+	Int,
+	/// Universal heroes.
+	All,
+}
+
+impl fmt::Display for Attribute {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Attribute::Str => "Strength",
+			Attribute::Agi => "Agility",
+			Attribute::Int => "Intelligence",
+			Attribute::All => "Universal",
+		})
+	}
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HeroInfo {
+	/// Internal name, e.g. `npc_dota_hero_antimage`.
+	pub name: String,
+	/// English name as the game shows it, e.g. `Anti-Mage`.
+	pub localized_name: String,
+	pub primary_attr: Attribute,
+	/// Portrait, relative to the CDN.
+	img: String,
+}
+
+impl HeroInfo {
+	pub fn portrait_url(&self) -> String {
+		return format!("{}{}", CDN, self.img);
+	}
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ItemInfo {
+	/// Internal name, e.g. `item_black_king_bar`.
+	pub name: String,
+	pub localized_name: String,
+	/// Icon, relative to the CDN.
+	img: String,
+}
+
+impl ItemInfo {
+	pub fn icon_url(&self) -> String {
+		return format!("{}{}", CDN, self.img);
+	}
+}
+
+/// Only abilities whose internal name doesn't title-case into the right one are listed.
+#[derive(Deserialize, Debug)]
+pub struct AbilityInfo {
+	/// Internal name, e.g. `nevermore_requiem`.
+	pub name: String,
+	pub localized_name: String,
+	/// Icon, relative to the CDN.
+	img: String,
+}
+
+impl AbilityInfo {
+	pub fn icon_url(&self) -> String {
+		return format!("{}{}", CDN, self.img);
+	}
+}
+
+struct Tables {
+	heroes: HashMap<String, HeroInfo>,
+	items: HashMap<String, ItemInfo>,
+	abilities: HashMap<String, AbilityInfo>,
+}
+
+fn tables() -> &'static Tables {
+	static TABLES: OnceLock<Tables> = OnceLock::new();
+
+	return TABLES.get_or_init(|| {
+		let heroes: Vec<HeroInfo> = serde_json::from_str(HEROES).expect("bundled hero table is invalid");
+		let items: Vec<ItemInfo> = serde_json::from_str(ITEMS).expect("bundled item table is invalid");
+		let abilities: Vec<AbilityInfo> = serde_json::from_str(ABILITIES).expect("bundled ability table is invalid");
+
+		Tables {
+			heroes: heroes.into_iter().map(|h| (h.name.clone(), h)).collect(),
+			items: items.into_iter().map(|i| (i.name.clone(), i)).collect(),
+			abilities: abilities.into_iter().map(|a| (a.name.clone(), a)).collect(),
+		}
+	});
+}
+
+pub fn hero(name: &str) -> Option<&'static HeroInfo> {
+	return tables().heroes.get(name);
+}
+
+pub fn item(name: &str) -> Option<&'static ItemInfo> {
+	return tables().items.get(name);
+}
+
+pub fn ability(name: &str) -> Option<&'static AbilityInfo> {
+	return tables().abilities.get(name);
+}
+
+/// A readable name for an internal hero name, e.g. `npc_dota_hero_antimage` becomes `Anti-Mage`.
+pub fn hero_name(name: &str) -> String {
+	return match hero(name) {
+		Some(hero) => hero.localized_name.clone(),
+		None => title_case(name.trim_start_matches(HERO_PREFIX)),
+	};
+}
+
+/// A readable name for an internal item name, e.g. `item_black_king_bar` becomes `Black King Bar`.
+pub fn item_name(name: &str) -> String {
+	return match item(name) {
+		Some(item) => item.localized_name.clone(),
+		None => title_case(name.trim_start_matches(ITEM_PREFIX)),
+	};
+}
+
+/// A readable name for an internal ability name, e.g. `antimage_mana_void` becomes `Mana Void`.
+pub fn ability_name(name: &str) -> String {
+	if let Some(ability) = ability(name) {
+		return ability.localized_name.clone();
+	}
+
+	// Abilities are prefixed with their hero's internal name, which may itself contain underscores.
+	let unprefixed = tables().heroes.keys()
+		.filter_map(|hero| name.strip_prefix(hero.trim_start_matches(HERO_PREFIX))?.strip_prefix('_'))
+		.min_by_key(|rest| rest.len())
+		.unwrap_or(name);
+
+	return title_case(unprefixed);
+}
+
+/// The icon of an item, if it is in the table.
+pub fn item_icon_url(name: &str) -> Option<String> {
+	return item(name).map(ItemInfo::icon_url);
+}
+
+/// The icon of an ability. The CDN names them after the internal name, so unlisted abilities have one too.
+pub fn ability_icon_url(name: &str) -> String {
+	return match ability(name) {
+		Some(ability) => ability.icon_url(),
+		None => format!("{}{}{}.png", CDN, ABILITY_ICONS, name),
+	};
+}
+
+/// Finds the hero a user-typed name refers to, by either its display or internal name.
+pub fn find_hero(query: &str) -> Option<&'static HeroInfo> {
+	return tables().heroes.values().find(|hero| hero_matches(&hero.name, query));
+}
+
+/// Whether a user-typed hero name refers to the internal name `hero`, ignoring case, spaces and punctuation.
+/// Both the display name (`Shadow Fiend`) and the internal one (`nevermore`) match.
+pub fn hero_matches(hero: &str, query: &str) -> bool {
+	let normalize = |s: &str| s.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect::<String>();
+	let query = normalize(query);
+
+	if query.is_empty() {
+		return false;
+	}
+
+	return normalize(hero.trim_start_matches(HERO_PREFIX)) == query || normalize(&hero_name(hero)) == query;
+}
+
+fn title_case(name: &str) -> String {
+	return name.split('_')
+		.filter(|word| !word.is_empty())
+		.map(|word| {
+			let mut chars = word.chars();
+			match chars.next() {
+				Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+				None => String::new(),
+			}
+		})
+		.collect::<Vec<_>>()
+		.join(" ");
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn loads_bundled_tables() {
+		assert!(!tables().heroes.is_empty());
+		assert!(!tables().items.is_empty());
+		assert!(!tables().abilities.is_empty());
+	}
+
+	#[test]
+	fn names_from_tables() {
+		assert_eq!(hero_name("npc_dota_hero_antimage"), "Anti-Mage");
+		assert_eq!(item_name("item_black_king_bar"), "Black King Bar");
+		assert_eq!(ability_name("nevermore_requiem"), "Requiem of Souls");
+		assert_eq!(hero("npc_dota_hero_antimage").unwrap().primary_attr, Attribute::Agi);
+	}
+
+	#[test]
+	fn names_fall_back_to_internal_ones() {
+		assert_eq!(hero_name("npc_dota_hero_some_new_hero"), "Some New Hero");
+		assert_eq!(item_name("item_some_new_item"), "Some New Item");
+		assert_eq!(ability_name("antimage_mana_break"), "Mana Break");
+		assert_eq!(ability_name("shadow_shaman_ether_shock"), "Ether Shock");
+		assert_eq!(ability_name("some_new_ability"), "Some New Ability");
+	}
+
+	#[test]
+	fn artwork_urls() {
+		assert_eq!(
+			hero("npc_dota_hero_antimage").unwrap().portrait_url(),
+			"https://cdn.cloudflare.steamstatic.com/apps/dota2/images/dota_react/heroes/antimage.png",
+		);
+		assert_eq!(
+			item_icon_url("item_black_king_bar").unwrap(),
+			"https://cdn.cloudflare.steamstatic.com/apps/dota2/images/dota_react/items/black_king_bar.png",
+		);
+		assert_eq!(item_icon_url("item_some_new_item"), None);
+		assert_eq!(
+			ability_icon_url("antimage_mana_break"),
+			"https://cdn.cloudflare.steamstatic.com/apps/dota2/images/dota_react/abilities/antimage_mana_break.png",
+		);
+	}
+
+	#[test]
+	fn finds_heroes_by_either_name() {
+		assert_eq!(find_hero("shadow fiend").unwrap().name, "npc_dota_hero_nevermore");
+		assert_eq!(find_hero("Nevermore").unwrap().name, "npc_dota_hero_nevermore");
+		assert_eq!(find_hero("anti mage").unwrap().name, "npc_dota_hero_antimage");
+		assert!(find_hero("").is_none());
+		assert!(find_hero("not a hero").is_none());
+	}
+}
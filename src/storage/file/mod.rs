@@ -0,0 +1,352 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rusty_ulid::Ulid;
+use serde::{Deserialize, Serialize};
+use serenity::CacheAndHttp;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+use crate::bot::SteamId;
+use crate::bot::events::EventFilter;
+use crate::bot::spectate::SpectateMode;
+use super::{MatchRecord, Snapshot, Storage, StorageError, UserInfo};
+
+mod persist;
+mod schema;
+
+pub use self::persist::PersistError;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GuildData {
+	channel: ChannelId,
+	events: EventFilter,
+}
+
+impl GuildData {
+	fn new(channel: ChannelId) -> Self {
+		Self {
+			channel,
+			events: EventFilter::default(),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveData {
+	guilds: HashMap<GuildId, GuildData>,
+	users: HashMap<UserInfo, UserId>,
+	tracks: HashMap<UserId, HashSet<GuildId>>,
+	matches: Vec<MatchRecord>,
+	spectators: HashMap<UserId, SpectateMode>,
+}
+
+impl SaveData {
+	fn new() -> Self {
+		Self {
+			guilds: HashMap::new(),
+			users: HashMap::new(),
+			tracks: HashMap::new(),
+			matches: Vec::new(),
+			spectators: HashMap::new(),
+		}
+	}
+}
+
+/// Keeps everything in memory and rewrites a single MessagePack file (`stalker.dat`) on every change.
+pub struct FileStorage {
+	path: PathBuf,
+	save: SaveData,
+}
+
+impl FileStorage {
+	/// Fails if the file exists but can't be read, rather than starting empty and overwriting it on the next change.
+	/// Data from older versions is migrated and written back, with the original kept as a backup.
+	pub async fn open(path: &Path, cah: &CacheAndHttp) -> Result<Self, StorageError> {
+		let (save, migrated) = match load(path, cah).await? {
+			Some(loaded) => loaded,
+			None => {
+				log::warn!("Could not find {}! (First run?)", path.display());
+				(SaveData::new(), false)
+			}
+		};
+
+		for (guild, guild_data) in &save.guilds {
+			log::debug!("Loaded binding for guild {}: {:?}", guild, guild_data);
+		}
+
+		for user in &save.users {
+			log::debug!("Loaded user {:#?}", user);
+		}
+
+		for track in &save.tracks {
+			log::debug!("Loaded tracks for user {}: {:#?}", track.0, track.1);
+		}
+
+		let storage = FileStorage {
+			path: path.to_owned(),
+			save,
+		};
+
+		if migrated {
+			storage.write()?;
+		}
+
+		Ok(storage)
+	}
+
+	/// Reads everything in the file without ever writing to it, migrating older versions in memory only.
+	/// Unlike [`FileStorage::open`], a missing file is an error.
+	pub async fn read_snapshot(path: &Path, cah: &CacheAndHttp) -> Result<Snapshot, StorageError> {
+		let (save, _) = load(path, cah).await?
+			.ok_or_else(|| PersistError::Io(io::Error::from(io::ErrorKind::NotFound)))?;
+
+		let storage = FileStorage {
+			path: path.to_owned(),
+			save,
+		};
+
+		storage.export()
+	}
+
+	fn write(&self) -> Result<(), StorageError> {
+		let bytes = schema::encode(&self.save)?;
+		persist::save(&self.path, &bytes)?;
+		Ok(())
+	}
+}
+
+impl Storage for FileStorage {
+	fn tokens(&self) -> Result<HashSet<Ulid>, StorageError> {
+		Ok(self.save.users.keys().map(|u| u.token).collect())
+	}
+
+	fn find_user(&self, info: &UserInfo) -> Result<Option<UserId>, StorageError> {
+		Ok(self.save.users.get(info).copied())
+	}
+
+	fn token_user(&self, token: Ulid) -> Result<Option<UserId>, StorageError> {
+		Ok(self.save.users.iter()
+			.find(|(info, _)| info.token == token)
+			.map(|(_, user)| *user))
+	}
+
+	fn steam_user(&self, steam_id: SteamId) -> Result<Option<UserId>, StorageError> {
+		Ok(self.save.users.iter()
+			.filter(|(info, _)| info.steam_id == steam_id)
+			.map(|(_, user)| *user)
+			.min())
+	}
+
+	fn user_steam_ids(&self, user: UserId) -> Result<HashSet<SteamId>, StorageError> {
+		Ok(self.save.users.iter()
+			.filter(|(_, id)| **id == user)
+			.map(|(info, _)| info.steam_id)
+			.collect())
+	}
+
+	fn register_user(&mut self, user: UserId, info: UserInfo) -> Result<(), StorageError> {
+		self.save.users.retain(|i, id| !(*id == user && i.steam_id == info.steam_id));
+		self.save.users.insert(info, user);
+		self.write()
+	}
+
+	fn rotate_token(&mut self, user: UserId, token: Ulid) -> Result<(), StorageError> {
+		let steam_ids = self.user_steam_ids(user)?;
+
+		self.save.users.retain(|_, id| *id != user);
+		for steam_id in steam_ids {
+			self.save.users.insert(UserInfo { token, steam_id }, user);
+		}
+
+		self.write()
+	}
+
+	fn remove_user(&mut self, user: UserId) -> Result<(), StorageError> {
+		self.save.users.retain(|_, id| *id != user);
+		self.save.tracks.remove(&user);
+		self.save.spectators.remove(&user);
+		self.write()
+	}
+
+	fn spectate_mode(&self, user: UserId) -> Result<SpectateMode, StorageError> {
+		Ok(self.save.spectators.get(&user).copied().unwrap_or_default())
+	}
+
+	fn set_spectate_mode(&mut self, user: UserId, mode: SpectateMode) -> Result<(), StorageError> {
+		if mode == SpectateMode::Off {
+			self.save.spectators.remove(&user);
+		} else {
+			self.save.spectators.insert(user, mode);
+		}
+		self.write()
+	}
+
+	fn guild_channel(&self, guild: GuildId) -> Result<Option<ChannelId>, StorageError> {
+		Ok(self.save.guilds.get(&guild).map(|g| g.channel))
+	}
+
+	fn bind_guild(&mut self, guild: GuildId, channel: ChannelId) -> Result<Option<ChannelId>, StorageError> {
+		let old = match self.save.guilds.get_mut(&guild) {
+			None => {
+				self.save.guilds.insert(guild, GuildData::new(channel));
+				None
+			}
+			Some(guild_data) => Some(std::mem::replace(&mut guild_data.channel, channel)),
+		};
+
+		self.write()?;
+		Ok(old)
+	}
+
+	fn guild_events(&self, guild: GuildId) -> Result<Option<EventFilter>, StorageError> {
+		Ok(self.save.guilds.get(&guild).map(|g| g.events))
+	}
+
+	fn set_guild_events(&mut self, guild: GuildId, events: EventFilter) -> Result<(), StorageError> {
+		match self.save.guilds.get_mut(&guild) {
+			None => Ok(()),
+			Some(guild_data) => {
+				guild_data.events = events;
+				self.write()
+			}
+		}
+	}
+
+	fn add_track(&mut self, user: UserId, guild: GuildId) -> Result<(), StorageError> {
+		self.save.tracks.entry(user).or_default().insert(guild);
+		self.write()
+	}
+
+	fn remove_track(&mut self, user: UserId, guild: GuildId) -> Result<(), StorageError> {
+		match self.save.tracks.get_mut(&user) {
+			None => Ok(()),
+			Some(tracks) => {
+				if !tracks.remove(&guild) {
+					return Ok(());
+				}
+
+				if tracks.is_empty() {
+					self.save.tracks.remove(&user);
+				}
+
+				self.write()
+			}
+		}
+	}
+
+	fn guild_users(&self, guild: GuildId) -> Result<Vec<UserId>, StorageError> {
+		Ok(self.save.tracks.iter()
+			.filter(|(_, guilds)| guilds.contains(&guild))
+			.map(|(user, _)| *user)
+			.collect())
+	}
+
+	fn track_channels(&self, user: UserId) -> Result<Vec<(ChannelId, EventFilter)>, StorageError> {
+		Ok(match self.save.tracks.get(&user) {
+			None => Vec::new(),
+			Some(tracks) => tracks.iter()
+				.filter_map(|guild| self.save.guilds.get(guild))
+				.map(|guild_data| (guild_data.channel, guild_data.events))
+				.collect(),
+		})
+	}
+
+	fn record_match(&mut self, record: MatchRecord) -> Result<(), StorageError> {
+		self.save.matches.retain(|m| !(m.match_id == record.match_id && m.steam_id == record.steam_id));
+		self.save.matches.push(record);
+		self.write()
+	}
+
+	fn user_matches(&self, user: UserId, offset: usize, limit: usize) -> Result<Vec<MatchRecord>, StorageError> {
+		let mut matches = self.save.matches.iter()
+			.filter(|m| m.user == user)
+			.cloned()
+			.collect::<Vec<_>>();
+
+		matches.sort_by_key(|m| std::cmp::Reverse(m.ended_at));
+
+		Ok(matches.into_iter().skip(offset).take(limit).collect())
+	}
+
+	fn user_matches_since(&self, user: UserId, since: i64) -> Result<Vec<MatchRecord>, StorageError> {
+		let mut matches = self.save.matches.iter()
+			.filter(|m| m.user == user && m.ended_at >= since)
+			.cloned()
+			.collect::<Vec<_>>();
+
+		matches.sort_by_key(|m| std::cmp::Reverse(m.ended_at));
+
+		Ok(matches)
+	}
+
+	fn export(&self) -> Result<Snapshot, StorageError> {
+		Ok(Snapshot {
+			users: self.save.users.iter().map(|(info, user)| (*info, *user)).collect(),
+			guilds: self.save.guilds.iter().map(|(guild, data)| (*guild, data.channel, data.events)).collect(),
+			tracks: self.save.tracks.iter()
+				.flat_map(|(user, guilds)| guilds.iter().map(move |guild| (*user, *guild)))
+				.collect(),
+			matches: self.save.matches.clone(),
+			spectators: self.save.spectators.iter().map(|(user, mode)| (*user, *mode)).collect(),
+		})
+	}
+
+	fn import(&mut self, snapshot: Snapshot) -> Result<(), StorageError> {
+		self.save.users.extend(snapshot.users);
+		for (guild, channel, events) in snapshot.guilds {
+			self.save.guilds.insert(guild, GuildData { channel, events });
+		}
+		for (user, guild) in snapshot.tracks {
+			self.save.tracks.entry(user).or_default().insert(guild);
+		}
+		self.save.matches.extend(snapshot.matches);
+		self.save.spectators.extend(snapshot.spectators);
+		self.write()
+	}
+}
+
+/// Decodes the file and brings it up to the current version, returning whether that took a migration.
+/// `None` if there is no file yet.
+async fn load(path: &Path, cah: &CacheAndHttp) -> Result<Option<(SaveData, bool)>, StorageError> {
+	let bytes = match persist::load(path)? {
+		Some(bytes) => bytes,
+		None => return Ok(None),
+	};
+
+	let data = schema::decode(&bytes)?;
+	let version = data.version();
+
+	if version == schema::CURRENT_VERSION {
+		return Ok(Some((data.upgrade(&HashMap::new()), false)));
+	}
+
+	log::info!("Migrating {} from version {} to {}", path.display(), version, schema::CURRENT_VERSION);
+	let channel_guilds = resolve_channel_guilds(cah, data.unresolved_channels()).await?;
+	Ok(Some((data.upgrade(&channel_guilds), true)))
+}
+
+/// Looks up which guild each channel belongs to, for migrating data from before bindings were per guild.
+///
+/// Channels Discord says are gone (or that aren't in a guild) are left out. Any other failure aborts, so that a network
+/// hiccup at startup can't silently drop bindings.
+async fn resolve_channel_guilds(cah: &CacheAndHttp, channels: Vec<ChannelId>) -> Result<HashMap<ChannelId, GuildId>, PersistError> {
+	let mut channel_guilds = HashMap::new();
+
+	for channel in channels {
+		match cah.http.get_channel(channel.0).await {
+			Ok(c) => match c.guild() {
+				Some(gc) => {
+					channel_guilds.insert(channel, gc.guild_id);
+				}
+				None => log::warn!("Channel {} is not a guild channel", channel),
+			},
+			Err(serenity::Error::Http(e)) if e.status_code().map(|s| s.as_u16()) == Some(404) => {
+				log::warn!("Channel {} no longer exists", channel);
+			}
+			Err(e) => return Err(PersistError::Migration(format!("could not look up channel {}: {}", channel, e))),
+		}
+	}
+
+	Ok(channel_guilds)
+}
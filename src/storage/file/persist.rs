@@ -0,0 +1,118 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use rmp_serde::{decode, encode};
+
+/// How many previous versions of the data file to keep around as `<file>.1` (newest) to `<file>.N` (oldest).
+const BACKUP_COUNT: usize = 3;
+
+#[derive(Debug)]
+pub enum PersistError {
+	Io(io::Error),
+	Encode(encode::Error),
+	Decode(decode::Error),
+	/// The file was written by a newer build whose schema we don't know.
+	UnknownVersion(u32),
+	/// Old data could not be brought up to date.
+	Migration(String),
+}
+
+impl fmt::Display for PersistError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			PersistError::Io(e) => write!(f, "I/O error: {}", e),
+			PersistError::Encode(e) => write!(f, "encoding error: {}", e),
+			PersistError::Decode(e) => write!(f, "decoding error: {}", e),
+			PersistError::UnknownVersion(v) => write!(f, "unknown schema version {}", v),
+			PersistError::Migration(why) => write!(f, "migration failed: {}", why),
+		}
+	}
+}
+
+impl From<io::Error> for PersistError {
+	fn from(e: io::Error) -> Self {
+		PersistError::Io(e)
+	}
+}
+
+impl From<encode::Error> for PersistError {
+	fn from(e: encode::Error) -> Self {
+		PersistError::Encode(e)
+	}
+}
+
+impl From<decode::Error> for PersistError {
+	fn from(e: decode::Error) -> Self {
+		PersistError::Decode(e)
+	}
+}
+
+/// Reads `path`, returning `Ok(None)` if it doesn't exist yet.
+pub fn load(path: &Path) -> Result<Option<Vec<u8>>, PersistError> {
+	match fs::read(path) {
+		Ok(bytes) => Ok(Some(bytes)),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Atomically replaces `path` with `bytes`.
+///
+/// The new contents are written and synced to a temporary file first, the current file is copied into the backup rotation,
+/// and only then is the temporary file renamed over it. A crash at any point leaves either the old or the new file in place.
+pub fn save(path: &Path, bytes: &[u8]) -> Result<(), PersistError> {
+	let tmp_path = with_suffix(path, "tmp");
+	{
+		let mut tmp = File::create(&tmp_path)?;
+		tmp.write_all(bytes)?;
+		tmp.sync_all()?;
+	}
+
+	if path.exists() {
+		rotate_backups(path)?;
+	}
+
+	fs::rename(&tmp_path, path)?;
+	sync_parent_dir(path)?;
+
+	Ok(())
+}
+
+fn rotate_backups(path: &Path) -> io::Result<()> {
+	for n in (1..BACKUP_COUNT).rev() {
+		let from = with_suffix(path, &n.to_string());
+		if from.exists() {
+			fs::rename(&from, with_suffix(path, &(n + 1).to_string()))?;
+		}
+	}
+
+	// Copy rather than rename, so the live file never goes missing.
+	fs::copy(path, with_suffix(path, "1"))?;
+
+	Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+	let mut name = path.as_os_str().to_owned();
+	name.push(".");
+	name.push(suffix);
+	PathBuf::from(name)
+}
+
+/// Makes the rename itself durable. Directories can't be opened for syncing on Windows, where this is a no-op.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+	let dir = match path.parent() {
+		Some(dir) if !dir.as_os_str().is_empty() => dir,
+		_ => Path::new("."),
+	};
+
+	File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+	Ok(())
+}
@@ -0,0 +1,440 @@
+//! On-disk layout of the save data and the migrations between its versions.
+//!
+//! Everything is written as an [`Envelope`] tagged with [`CURRENT_VERSION`]. Files from before the envelope existed are
+//! recognised by their shape. When the layout of [`SaveData`] changes, freeze the old one here as `SaveDataVn`, bump the
+//! version, and add a step to [`Versioned::upgrade`].
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use rmp_serde::{decode, encode};
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+use crate::storage::{MatchRecord, UserInfo};
+use super::{GuildData, SaveData};
+use super::persist::PersistError;
+
+pub const CURRENT_VERSION: u32 = 6;
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+	version: u32,
+	#[serde(with = "serde_bytes")]
+	data: Vec<u8>,
+}
+
+/// Version 1, unversioned: one global set of bound channels, with tracks pointing straight at channels.
+#[derive(Deserialize)]
+pub struct SaveDataV1 {
+	channels: HashSet<ChannelId>,
+	users: HashMap<UserInfo, UserId>,
+	tracks: HashMap<UserId, HashSet<ChannelId>>,
+}
+
+/// Version 2, unversioned: bindings and tracks keyed by guild.
+#[derive(Deserialize)]
+pub struct SaveDataV2 {
+	guilds: HashMap<GuildId, GuildDataV2>,
+	users: HashMap<UserInfo, UserId>,
+	tracks: HashMap<UserId, HashSet<GuildId>>,
+}
+
+#[derive(Deserialize)]
+struct GuildDataV2 {
+	channel: ChannelId,
+}
+
+/// Version 3: the version 2 layout, now in an envelope.
+#[derive(Deserialize)]
+pub struct SaveDataV3 {
+	guilds: HashMap<GuildId, GuildDataV2>,
+	users: HashMap<UserInfo, UserId>,
+	tracks: HashMap<UserId, HashSet<GuildId>>,
+}
+
+/// Version 4: adds match history.
+#[derive(Deserialize)]
+pub struct SaveDataV4 {
+	guilds: HashMap<GuildId, GuildDataV2>,
+	users: HashMap<UserInfo, UserId>,
+	tracks: HashMap<UserId, HashSet<GuildId>>,
+	matches: Vec<MatchRecord>,
+}
+
+/// Version 5: adds per-guild event settings.
+#[derive(Deserialize)]
+pub struct SaveDataV5 {
+	guilds: HashMap<GuildId, GuildData>,
+	users: HashMap<UserInfo, UserId>,
+	tracks: HashMap<UserId, HashSet<GuildId>>,
+	matches: Vec<MatchRecord>,
+}
+
+pub enum Versioned {
+	V1(SaveDataV1),
+	V2(SaveDataV2),
+	V3(SaveDataV3),
+	V4(SaveDataV4),
+	V5(SaveDataV5),
+	V6(SaveData),
+}
+
+impl Versioned {
+	pub fn version(&self) -> u32 {
+		match self {
+			Versioned::V1(_) => 1,
+			Versioned::V2(_) => 2,
+			Versioned::V3(_) => 3,
+			Versioned::V4(_) => 4,
+			Versioned::V5(_) => 5,
+			Versioned::V6(_) => 6,
+		}
+	}
+
+	/// Channels whose guild has to be looked up on Discord before [`Versioned::upgrade`] can place them.
+	pub fn unresolved_channels(&self) -> Vec<ChannelId> {
+		match self {
+			Versioned::V1(v1) => {
+				let mut channels = v1.channels.iter()
+					.chain(v1.tracks.values().flatten())
+					.copied()
+					.collect::<Vec<_>>();
+				channels.sort();
+				channels.dedup();
+				channels
+			}
+			_ => Vec::new(),
+		}
+	}
+
+	/// Runs every migration step needed to reach the current layout.
+	///
+	/// `channel_guilds` maps the channels from [`Versioned::unresolved_channels`] to their guilds. Channels missing from it
+	/// are assumed to be gone and are dropped.
+	pub fn upgrade(self, channel_guilds: &HashMap<ChannelId, GuildId>) -> SaveData {
+		let mut data = self;
+
+		loop {
+			data = match data {
+				Versioned::V1(v1) => Versioned::V2(v1_to_v2(v1, channel_guilds)),
+				Versioned::V2(v2) => Versioned::V3(v2_to_v3(v2)),
+				Versioned::V3(v3) => Versioned::V4(v3_to_v4(v3)),
+				Versioned::V4(v4) => Versioned::V5(v4_to_v5(v4)),
+				Versioned::V5(v5) => Versioned::V6(v5_to_v6(v5)),
+				Versioned::V6(save) => return save,
+			};
+		}
+	}
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Versioned, PersistError> {
+	let envelope = match decode::from_slice::<Envelope>(bytes) {
+		Ok(envelope) => envelope,
+		Err(e) => {
+			// Not enveloped, so it may predate versioning. Newest layout first.
+			if let Ok(v2) = decode::from_slice::<SaveDataV2>(bytes) {
+				return Ok(Versioned::V2(v2));
+			}
+
+			if let Ok(v1) = decode::from_slice::<SaveDataV1>(bytes) {
+				return Ok(Versioned::V1(v1));
+			}
+
+			return Err(e.into());
+		}
+	};
+
+	match envelope.version {
+		3 => Ok(Versioned::V3(decode::from_slice(&envelope.data)?)),
+		4 => Ok(Versioned::V4(decode::from_slice(&envelope.data)?)),
+		5 => Ok(Versioned::V5(decode::from_slice(&envelope.data)?)),
+		6 => Ok(Versioned::V6(decode::from_slice(&envelope.data)?)),
+		v => Err(PersistError::UnknownVersion(v)),
+	}
+}
+
+pub fn encode(save: &SaveData) -> Result<Vec<u8>, PersistError> {
+	let envelope = Envelope {
+		version: CURRENT_VERSION,
+		data: encode::to_vec(save)?,
+	};
+
+	Ok(encode::to_vec(&envelope)?)
+}
+
+/// Groups the global channels by guild. Guilds only get one output channel now, so if several of a guild's channels were
+/// bound, the lowest ID wins and tracks on any of them move to the guild.
+fn v1_to_v2(old: SaveDataV1, channel_guilds: &HashMap<ChannelId, GuildId>) -> SaveDataV2 {
+	let mut guilds: BTreeMap<GuildId, ChannelId> = BTreeMap::new();
+
+	let mut channels = old.channels.into_iter().collect::<Vec<_>>();
+	channels.sort();
+
+	for channel in channels {
+		match channel_guilds.get(&channel) {
+			Some(guild) => {
+				guilds.entry(*guild).or_insert(channel);
+			}
+			None => log::warn!("Dropping binding to channel {}, which no longer belongs to a guild", channel),
+		}
+	}
+
+	let tracks = old.tracks.into_iter()
+		.map(|(user, channels)| {
+			let guilds = channels.iter()
+				.filter_map(|channel| channel_guilds.get(channel))
+				.filter(|guild| guilds.contains_key(guild))
+				.copied()
+				.collect::<HashSet<_>>();
+			(user, guilds)
+		})
+		.filter(|(_, guilds)| !guilds.is_empty())
+		.collect();
+
+	SaveDataV2 {
+		guilds: guilds.into_iter()
+			.map(|(guild, channel)| (guild, GuildDataV2 { channel }))
+			.collect(),
+		users: old.users,
+		tracks,
+	}
+}
+
+fn v2_to_v3(old: SaveDataV2) -> SaveDataV3 {
+	SaveDataV3 {
+		guilds: old.guilds,
+		users: old.users,
+		tracks: old.tracks,
+	}
+}
+
+/// Adds match history, starting out empty.
+fn v3_to_v4(old: SaveDataV3) -> SaveDataV4 {
+	SaveDataV4 {
+		guilds: old.guilds,
+		users: old.users,
+		tracks: old.tracks,
+		matches: Vec::new(),
+	}
+}
+
+/// Adds per-guild event settings, with every event announced.
+fn v4_to_v5(old: SaveDataV4) -> SaveDataV5 {
+	SaveDataV5 {
+		guilds: old.guilds.into_iter()
+			.map(|(guild, data)| (guild, GuildData::new(data.channel)))
+			.collect(),
+		users: old.users,
+		tracks: old.tracks,
+		matches: old.matches,
+	}
+}
+
+/// Adds spectate settings, with spectating off for everyone.
+fn v5_to_v6(old: SaveDataV5) -> SaveData {
+	SaveData {
+		guilds: old.guilds,
+		users: old.users,
+		tracks: old.tracks,
+		matches: old.matches,
+		spectators: HashMap::new(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::bot::events::{EventFilter, EventKind};
+	use crate::bot::spectate::SpectateMode;
+
+	const V1: &[u8] = include_bytes!("../../../fixtures/save_v1.dat");
+	const V2: &[u8] = include_bytes!("../../../fixtures/save_v2.dat");
+	const V3: &[u8] = include_bytes!("../../../fixtures/save_v3.dat");
+	const V4: &[u8] = include_bytes!("../../../fixtures/save_v4.dat");
+	const V5: &[u8] = include_bytes!("../../../fixtures/save_v5.dat");
+	const V6: &[u8] = include_bytes!("../../../fixtures/save_v6.dat");
+
+	fn guilds(save: &SaveData) -> BTreeMap<u64, u64> {
+		save.guilds.iter().map(|(g, d)| (g.0, d.channel.0)).collect()
+	}
+
+	fn users(save: &SaveData) -> BTreeMap<(String, u64), u64> {
+		save.users.iter().map(|(info, user)| ((info.token.to_string(), info.steam_id), user.0)).collect()
+	}
+
+	/// Every fixture has user 1 on one account, and user 2 on two accounts sharing a token.
+	fn expected_users() -> BTreeMap<(String, u64), u64> {
+		[
+			("01ARZ3NDEKTSV4RRFFQ69G5FAV", 76561197960287930, 1),
+			("01BX5ZZKBKACTAV9WEVGEMMVRZ", 76561197960287931, 2),
+			("01BX5ZZKBKACTAV9WEVGEMMVRZ", 76561198000000000, 2),
+		].into_iter()
+			.map(|(token, steam_id, user)| ((token.to_owned(), steam_id), user))
+			.collect()
+	}
+
+	fn tracks(save: &SaveData) -> BTreeMap<u64, Vec<u64>> {
+		save.tracks.iter()
+			.map(|(u, gs)| {
+				let mut gs = gs.iter().map(|g| g.0).collect::<Vec<_>>();
+				gs.sort();
+				(u.0, gs)
+			})
+			.collect()
+	}
+
+	#[test]
+	fn loads_v1() {
+		let data = decode(V1).unwrap();
+		assert_eq!(data.version(), 1);
+
+		let unresolved = data.unresolved_channels().iter().map(|c| c.0).collect::<Vec<_>>();
+		assert_eq!(unresolved, vec![100, 101, 200, 300]);
+
+		// Channel 300 is gone, so it is left out.
+		let channel_guilds = [(100, 10), (101, 10), (200, 20)].into_iter()
+			.map(|(c, g)| (ChannelId(c), GuildId(g)))
+			.collect();
+
+		let save = data.upgrade(&channel_guilds);
+		assert_eq!(guilds(&save), [(10, 100), (20, 200)].into_iter().collect());
+		assert_eq!(tracks(&save), [(1, vec![10]), (2, vec![10, 20])].into_iter().collect());
+		assert_eq!(users(&save), expected_users());
+	}
+
+	/// What the unversioned build before any of this wrote, with tokens in rusty_ulid's own serde form.
+	#[test]
+	fn loads_tokens_written_by_rusty_ulid() {
+		use std::str::FromStr;
+
+		use rusty_ulid::Ulid;
+
+		#[derive(Serialize, PartialEq, Eq, Hash)]
+		struct OldUserInfo {
+			token: Ulid,
+			steam_id: u64,
+		}
+
+		#[derive(Serialize)]
+		struct OldSaveData {
+			channels: HashSet<ChannelId>,
+			users: HashMap<OldUserInfo, UserId>,
+			tracks: HashMap<UserId, HashSet<ChannelId>>,
+		}
+
+		let old = OldSaveData {
+			channels: [ChannelId(100)].into_iter().collect(),
+			users: expected_users().into_iter()
+				.map(|((token, steam_id), user)| (OldUserInfo { token: Ulid::from_str(&token).unwrap(), steam_id }, UserId(user)))
+				.collect(),
+			tracks: [(UserId(1), [ChannelId(100)].into_iter().collect())].into_iter().collect(),
+		};
+
+		let data = decode(&encode::to_vec(&old).unwrap()).unwrap();
+		assert_eq!(data.version(), 1);
+
+		let save = data.upgrade(&[(ChannelId(100), GuildId(10))].into_iter().collect());
+		assert_eq!(users(&save), expected_users());
+		assert_eq!(tracks(&save), [(1, vec![10])].into_iter().collect());
+	}
+
+	#[test]
+	fn loads_v2() {
+		let data = decode(V2).unwrap();
+		assert_eq!(data.version(), 2);
+		assert!(data.unresolved_channels().is_empty());
+
+		let save = data.upgrade(&HashMap::new());
+		assert_eq!(guilds(&save), [(10, 100), (20, 200)].into_iter().collect());
+		assert_eq!(tracks(&save), [(1, vec![10]), (2, vec![20])].into_iter().collect());
+		assert_eq!(users(&save), expected_users());
+	}
+
+	#[test]
+	fn loads_v3() {
+		let data = decode(V3).unwrap();
+		assert_eq!(data.version(), 3);
+
+		let save = data.upgrade(&HashMap::new());
+		assert_eq!(guilds(&save), [(10, 100), (20, 200)].into_iter().collect());
+		assert_eq!(tracks(&save), [(1, vec![10]), (2, vec![20])].into_iter().collect());
+		assert_eq!(users(&save), expected_users());
+		assert!(save.matches.is_empty());
+	}
+
+	#[test]
+	fn loads_v4() {
+		let data = decode(V4).unwrap();
+		assert_eq!(data.version(), 4);
+
+		let save = data.upgrade(&HashMap::new());
+		assert_eq!(guilds(&save), [(10, 100), (20, 200)].into_iter().collect());
+		assert_eq!(tracks(&save), [(1, vec![10]), (2, vec![20])].into_iter().collect());
+		assert_eq!(users(&save), expected_users());
+		assert_eq!(save.matches.len(), 1);
+		assert_eq!(save.matches[0].match_id, 6800000000);
+		assert_eq!(save.matches[0].hero, "npc_dota_hero_antimage");
+		assert_eq!(save.matches[0].won, Some(true));
+		assert_eq!(save.guilds[&GuildId(20)].events, EventFilter::default());
+	}
+
+	#[test]
+	fn loads_v5() {
+		let data = decode(V5).unwrap();
+		assert_eq!(data.version(), 5);
+
+		let save = data.upgrade(&HashMap::new());
+		assert_eq!(guilds(&save), [(10, 100), (20, 200)].into_iter().collect());
+		assert_eq!(tracks(&save), [(1, vec![10]), (2, vec![20])].into_iter().collect());
+		assert_eq!(users(&save), expected_users());
+		assert_eq!(save.matches.len(), 1);
+
+		// Guild 20 muted deaths.
+		let events = save.guilds[&GuildId(20)].events;
+		assert!(!events.allows(EventKind::Death));
+		assert!(events.allows(EventKind::FirstBlood));
+		assert_eq!(save.guilds[&GuildId(10)].events, EventFilter::default());
+		assert!(save.spectators.is_empty());
+	}
+
+	#[test]
+	fn loads_v6() {
+		let data = decode(V6).unwrap();
+		assert_eq!(data.version(), 6);
+
+		let save = data.upgrade(&HashMap::new());
+		assert_eq!(guilds(&save), [(10, 100), (20, 200)].into_iter().collect());
+		assert_eq!(tracks(&save), [(1, vec![10]), (2, vec![20])].into_iter().collect());
+		assert_eq!(users(&save), expected_users());
+		assert!(!save.guilds[&GuildId(20)].events.allows(EventKind::Death));
+
+		// User 2 posts scoreboards.
+		assert_eq!(save.spectators.len(), 1);
+		assert_eq!(save.spectators[&UserId(2)], SpectateMode::Scoreboard);
+	}
+
+	#[test]
+	fn round_trips_current_version() {
+		let save = decode(V6).unwrap().upgrade(&HashMap::new());
+		let data = decode(&encode(&save).unwrap()).unwrap();
+		assert_eq!(data.version(), CURRENT_VERSION);
+		let reloaded = data.upgrade(&HashMap::new());
+		assert_eq!(guilds(&reloaded), guilds(&save));
+		assert_eq!(users(&reloaded), expected_users());
+	}
+
+	#[test]
+	fn rejects_unknown_version() {
+		let envelope = Envelope {
+			version: CURRENT_VERSION + 1,
+			data: Vec::new(),
+		};
+		let bytes = encode::to_vec(&envelope).unwrap();
+		assert!(matches!(decode(&bytes), Err(PersistError::UnknownVersion(_))));
+	}
+
+	#[test]
+	fn rejects_garbage() {
+		assert!(decode(b"definitely not msgpack").is_err());
+	}
+}
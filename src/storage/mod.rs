@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use rusty_ulid::Ulid;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+use crate::bot::SteamId;
+use crate::bot::events::EventFilter;
+use crate::bot::spectate::SpectateMode;
+
+mod file;
+mod sqlite;
+
+pub use self::file::{FileStorage, PersistError};
+pub use self::sqlite::SqliteStorage;
+
+#[derive(Serialize, Deserialize, Debug, Eq, Hash, PartialEq, Copy, Clone)]
+pub struct UserInfo {
+	#[serde(with = "token_string")]
+	pub token: Ulid,
+	pub steam_id: SteamId,
+}
+
+/// Saves tokens as their canonical 26-character string, the same text the SQLite backend and the cfg files use,
+/// so the save format is pinned down here rather than by whatever rusty_ulid's serde support does.
+mod token_string {
+	use std::str::FromStr;
+
+	use rusty_ulid::Ulid;
+	use serde::{de, Deserialize, Deserializer, Serializer};
+
+	/// Files written before this module existed went through rusty_ulid's own `Serialize`, so that form is read too.
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum Stored {
+		Text(String),
+		Native(Ulid),
+	}
+
+	pub fn serialize<S: Serializer>(token: &Ulid, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(token)
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Ulid, D::Error> {
+		match Stored::deserialize(deserializer)? {
+			Stored::Text(token) => Ulid::from_str(&token).map_err(de::Error::custom),
+			Stored::Native(token) => Ok(token),
+		}
+	}
+}
+
+/// The final state of one tracked player in one match.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MatchRecord {
+	pub match_id: u64,
+	pub steam_id: SteamId,
+	pub user: UserId,
+	pub hero: String,
+	pub kills: u32,
+	pub deaths: u32,
+	pub assists: u32,
+	pub last_hits: u32,
+	pub denies: u32,
+	pub gpm: u32,
+	pub xpm: u32,
+	pub net_worth: Option<u32>,
+	/// Game clock at the end of the match, in seconds.
+	pub duration: i32,
+	pub team: String,
+	/// `None` if the client stopped reporting before the match was decided.
+	pub won: Option<bool>,
+	/// Unix timestamp of when the match was recorded.
+	pub ended_at: i64,
+}
+
+/// Everything a backend holds, used to move data between backends.
+#[derive(Default)]
+pub struct Snapshot {
+	pub users: Vec<(UserInfo, UserId)>,
+	pub guilds: Vec<(GuildId, ChannelId, EventFilter)>,
+	pub tracks: Vec<(UserId, GuildId)>,
+	pub matches: Vec<MatchRecord>,
+	/// Users whose spectate mode isn't [`SpectateMode::Off`].
+	pub spectators: Vec<(UserId, SpectateMode)>,
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+	File(PersistError),
+	Sqlite(rusqlite::Error),
+	/// The database was migrated by a newer build than this one.
+	UnknownSchema(usize),
+}
+
+impl fmt::Display for StorageError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			StorageError::File(e) => write!(f, "{}", e),
+			StorageError::Sqlite(e) => write!(f, "SQLite error: {}", e),
+			StorageError::UnknownSchema(v) => write!(f, "unknown database schema version {}", v),
+		}
+	}
+}
+
+impl From<PersistError> for StorageError {
+	fn from(e: PersistError) -> Self {
+		StorageError::File(e)
+	}
+}
+
+impl From<rusqlite::Error> for StorageError {
+	fn from(e: rusqlite::Error) -> Self {
+		StorageError::Sqlite(e)
+	}
+}
+
+/// Where the bot keeps registrations, bindings, tracks and match history.
+///
+/// Mutations are durable once they return `Ok`. On `Err` the backend may or may not have applied the change.
+pub trait Storage: Send {
+	/// Every auth token currently in use.
+	fn tokens(&self) -> Result<HashSet<Ulid>, StorageError>;
+
+	/// The Discord user a token and Steam account pair is registered to.
+	fn find_user(&self, info: &UserInfo) -> Result<Option<UserId>, StorageError>;
+
+	/// The Discord user a token belongs to, whichever of their Steam accounts it is used from.
+	fn token_user(&self, token: Ulid) -> Result<Option<UserId>, StorageError>;
+
+	/// The Discord user who registered the Steam account, if any did.
+	/// Data from before registrations were exclusive can have several; the lowest user ID wins.
+	fn steam_user(&self, steam_id: SteamId) -> Result<Option<UserId>, StorageError>;
+
+	/// All Steam accounts the given Discord user has registered.
+	fn user_steam_ids(&self, user: UserId) -> Result<HashSet<SteamId>, StorageError>;
+
+	/// Adds a registration, replacing any earlier token for the same user and Steam account.
+	fn register_user(&mut self, user: UserId, info: UserInfo) -> Result<(), StorageError>;
+
+	/// Replaces every token the user holds with `token`.
+	fn rotate_token(&mut self, user: UserId, token: Ulid) -> Result<(), StorageError>;
+
+	/// Removes the user's registrations, tracks and settings.
+	fn remove_user(&mut self, user: UserId) -> Result<(), StorageError>;
+
+	fn spectate_mode(&self, user: UserId) -> Result<SpectateMode, StorageError>;
+
+	fn set_spectate_mode(&mut self, user: UserId, mode: SpectateMode) -> Result<(), StorageError>;
+
+	fn guild_channel(&self, guild: GuildId) -> Result<Option<ChannelId>, StorageError>;
+
+	/// Sets the guild's output channel, returning the one it replaced. Event settings are kept.
+	fn bind_guild(&mut self, guild: GuildId, channel: ChannelId) -> Result<Option<ChannelId>, StorageError>;
+
+	/// The events the guild announces, or `None` if it isn't bound.
+	fn guild_events(&self, guild: GuildId) -> Result<Option<EventFilter>, StorageError>;
+
+	/// Does nothing if the guild isn't bound.
+	fn set_guild_events(&mut self, guild: GuildId, events: EventFilter) -> Result<(), StorageError>;
+
+	fn add_track(&mut self, user: UserId, guild: GuildId) -> Result<(), StorageError>;
+
+	fn remove_track(&mut self, user: UserId, guild: GuildId) -> Result<(), StorageError>;
+
+	/// Users tracked in the guild.
+	fn guild_users(&self, guild: GuildId) -> Result<Vec<UserId>, StorageError>;
+
+	/// The channels the user's matches should be posted in, with the events each one announces.
+	fn track_channels(&self, user: UserId) -> Result<Vec<(ChannelId, EventFilter)>, StorageError>;
+
+	/// Stores a match result, replacing any earlier record for the same match and Steam account.
+	fn record_match(&mut self, record: MatchRecord) -> Result<(), StorageError>;
+
+	/// The user's recorded matches, most recent first.
+	fn user_matches(&self, user: UserId, offset: usize, limit: usize) -> Result<Vec<MatchRecord>, StorageError>;
+
+	/// All of the user's matches recorded at or after the `since` timestamp, most recent first.
+	fn user_matches_since(&self, user: UserId, since: i64) -> Result<Vec<MatchRecord>, StorageError>;
+
+	fn export(&self) -> Result<Snapshot, StorageError>;
+
+	/// Adds everything in `snapshot` to this backend.
+	fn import(&mut self, snapshot: Snapshot) -> Result<(), StorageError>;
+}
@@ -0,0 +1,544 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::str::FromStr;
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use rusty_ulid::Ulid;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+use crate::bot::SteamId;
+use crate::bot::events::EventFilter;
+use crate::bot::spectate::SpectateMode;
+use super::{MatchRecord, Snapshot, Storage, StorageError, UserInfo};
+
+/// Schema migrations, applied in order. The database's `user_version` records how many have run.
+/// Never edit one that has shipped; add a new one instead.
+const MIGRATIONS: &[&str] = &[
+	"CREATE TABLE users (
+		token TEXT NOT NULL,
+		steam_id INTEGER NOT NULL,
+		user_id INTEGER NOT NULL,
+		PRIMARY KEY (token, steam_id)
+	);
+	CREATE INDEX users_user_id ON users (user_id);
+	CREATE TABLE guilds (
+		guild_id INTEGER PRIMARY KEY,
+		channel_id INTEGER NOT NULL
+	);
+	CREATE TABLE tracks (
+		user_id INTEGER NOT NULL,
+		guild_id INTEGER NOT NULL,
+		PRIMARY KEY (user_id, guild_id)
+	);
+	CREATE TABLE matches (
+		match_id INTEGER NOT NULL,
+		steam_id INTEGER NOT NULL,
+		user_id INTEGER NOT NULL,
+		hero TEXT NOT NULL,
+		kills INTEGER NOT NULL,
+		deaths INTEGER NOT NULL,
+		assists INTEGER NOT NULL,
+		last_hits INTEGER NOT NULL,
+		denies INTEGER NOT NULL,
+		gpm INTEGER NOT NULL,
+		xpm INTEGER NOT NULL,
+		net_worth INTEGER,
+		duration INTEGER NOT NULL,
+		team TEXT NOT NULL,
+		won INTEGER,
+		ended_at INTEGER NOT NULL,
+		PRIMARY KEY (match_id, steam_id)
+	);
+	CREATE INDEX matches_user_ended ON matches (user_id, ended_at);",
+	"ALTER TABLE guilds ADD COLUMN muted_events INTEGER NOT NULL DEFAULT 0;",
+	"CREATE TABLE spectators (
+		user_id INTEGER PRIMARY KEY,
+		mode TEXT NOT NULL
+	);",
+];
+
+const MATCH_COLUMNS: &str = "match_id, steam_id, user_id, hero, kills, deaths, assists, last_hits, denies, gpm, xpm, net_worth, duration, team, won, ended_at";
+
+/// An embedded SQLite database (`stalker.db`).
+pub struct SqliteStorage {
+	conn: Connection,
+}
+
+impl SqliteStorage {
+	pub fn open(path: &Path) -> Result<Self, StorageError> {
+		let mut conn = Connection::open(path)?;
+		conn.pragma_update(None, "journal_mode", "WAL")?;
+
+		migrate(&mut conn)?;
+
+		Ok(SqliteStorage {
+			conn,
+		})
+	}
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), StorageError> {
+	let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+	if version > MIGRATIONS.len() {
+		return Err(StorageError::UnknownSchema(version));
+	}
+
+	for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+		log::info!("Applying database migration {}", i + 1);
+
+		let tx = conn.transaction()?;
+		tx.execute_batch(migration)?;
+		tx.pragma_update(None, "user_version", i + 1)?;
+		tx.commit()?;
+	}
+
+	Ok(())
+}
+
+// SQLite integers are signed. Snowflakes and SteamID64s both fit in 63 bits, so the casts below are lossless.
+
+fn id(row: &Row, idx: usize) -> rusqlite::Result<u64> {
+	row.get::<_, i64>(idx).map(|v| v as u64)
+}
+
+fn user_info(row: &Row) -> rusqlite::Result<UserInfo> {
+	let token: String = row.get(0)?;
+	let token = Ulid::from_str(&token)
+		.map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))?;
+
+	Ok(UserInfo {
+		token,
+		steam_id: id(row, 1)?,
+	})
+}
+
+fn spectate_mode(row: &Row, idx: usize) -> rusqlite::Result<SpectateMode> {
+	let mode: String = row.get(idx)?;
+	SpectateMode::from_name(&mode)
+		.ok_or_else(|| rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, format!("unknown spectate mode {}", mode).into()))
+}
+
+fn match_record(row: &Row) -> rusqlite::Result<MatchRecord> {
+	Ok(MatchRecord {
+		match_id: id(row, 0)?,
+		steam_id: id(row, 1)?,
+		user: UserId(id(row, 2)?),
+		hero: row.get(3)?,
+		kills: row.get(4)?,
+		deaths: row.get(5)?,
+		assists: row.get(6)?,
+		last_hits: row.get(7)?,
+		denies: row.get(8)?,
+		gpm: row.get(9)?,
+		xpm: row.get(10)?,
+		net_worth: row.get(11)?,
+		duration: row.get(12)?,
+		team: row.get(13)?,
+		won: row.get(14)?,
+		ended_at: row.get(15)?,
+	})
+}
+
+fn insert_match(conn: &Connection, m: &MatchRecord) -> rusqlite::Result<()> {
+	conn.execute(
+		&format!("INSERT OR REPLACE INTO matches ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)", MATCH_COLUMNS),
+		params![
+			m.match_id as i64, m.steam_id as i64, m.user.0 as i64, m.hero,
+			m.kills, m.deaths, m.assists, m.last_hits, m.denies, m.gpm, m.xpm, m.net_worth,
+			m.duration, m.team, m.won, m.ended_at,
+		],
+	)?;
+	Ok(())
+}
+
+/// Stores `info` as `user`'s registration of that Steam account, replacing the token it had before.
+fn insert_user(conn: &Connection, user: UserId, info: &UserInfo) -> rusqlite::Result<()> {
+	conn.execute("DELETE FROM users WHERE user_id = ?1 AND steam_id = ?2", params![user.0 as i64, info.steam_id as i64])?;
+	conn.execute(
+		"INSERT OR REPLACE INTO users (token, steam_id, user_id) VALUES (?1, ?2, ?3)",
+		params![info.token.to_string(), info.steam_id as i64, user.0 as i64],
+	)?;
+	Ok(())
+}
+
+impl Storage for SqliteStorage {
+	fn tokens(&self) -> Result<HashSet<Ulid>, StorageError> {
+		let mut stmt = self.conn.prepare("SELECT token, steam_id FROM users")?;
+		let tokens = stmt.query_map([], user_info)?
+			.map(|info| info.map(|i| i.token))
+			.collect::<rusqlite::Result<_>>()?;
+		Ok(tokens)
+	}
+
+	fn find_user(&self, info: &UserInfo) -> Result<Option<UserId>, StorageError> {
+		let user = self.conn.query_row(
+			"SELECT user_id FROM users WHERE token = ?1 AND steam_id = ?2",
+			params![info.token.to_string(), info.steam_id as i64],
+			|row| id(row, 0),
+		).optional()?;
+		Ok(user.map(UserId))
+	}
+
+	fn token_user(&self, token: Ulid) -> Result<Option<UserId>, StorageError> {
+		let user = self.conn.query_row(
+			"SELECT user_id FROM users WHERE token = ?1 LIMIT 1",
+			params![token.to_string()],
+			|row| id(row, 0),
+		).optional()?;
+		Ok(user.map(UserId))
+	}
+
+	fn steam_user(&self, steam_id: SteamId) -> Result<Option<UserId>, StorageError> {
+		let user = self.conn.query_row(
+			"SELECT user_id FROM users WHERE steam_id = ?1 ORDER BY user_id LIMIT 1",
+			params![steam_id as i64],
+			|row| id(row, 0),
+		).optional()?;
+		Ok(user.map(UserId))
+	}
+
+	fn user_steam_ids(&self, user: UserId) -> Result<HashSet<SteamId>, StorageError> {
+		let mut stmt = self.conn.prepare("SELECT steam_id FROM users WHERE user_id = ?1")?;
+		let ids = stmt.query_map(params![user.0 as i64], |row| id(row, 0))?
+			.collect::<rusqlite::Result<_>>()?;
+		Ok(ids)
+	}
+
+	fn register_user(&mut self, user: UserId, info: UserInfo) -> Result<(), StorageError> {
+		let tx = self.conn.transaction()?;
+		insert_user(&tx, user, &info)?;
+		tx.commit()?;
+		Ok(())
+	}
+
+	fn rotate_token(&mut self, user: UserId, token: Ulid) -> Result<(), StorageError> {
+		self.conn.execute("UPDATE users SET token = ?1 WHERE user_id = ?2", params![token.to_string(), user.0 as i64])?;
+		Ok(())
+	}
+
+	fn remove_user(&mut self, user: UserId) -> Result<(), StorageError> {
+		let tx = self.conn.transaction()?;
+		tx.execute("DELETE FROM users WHERE user_id = ?1", params![user.0 as i64])?;
+		tx.execute("DELETE FROM tracks WHERE user_id = ?1", params![user.0 as i64])?;
+		tx.execute("DELETE FROM spectators WHERE user_id = ?1", params![user.0 as i64])?;
+		tx.commit()?;
+		Ok(())
+	}
+
+	fn spectate_mode(&self, user: UserId) -> Result<SpectateMode, StorageError> {
+		let mode = self.conn.query_row(
+			"SELECT mode FROM spectators WHERE user_id = ?1",
+			params![user.0 as i64],
+			|row| spectate_mode(row, 0),
+		).optional()?;
+		Ok(mode.unwrap_or_default())
+	}
+
+	fn set_spectate_mode(&mut self, user: UserId, mode: SpectateMode) -> Result<(), StorageError> {
+		if mode == SpectateMode::Off {
+			self.conn.execute("DELETE FROM spectators WHERE user_id = ?1", params![user.0 as i64])?;
+		} else {
+			self.conn.execute(
+				"INSERT OR REPLACE INTO spectators (user_id, mode) VALUES (?1, ?2)",
+				params![user.0 as i64, mode.name()],
+			)?;
+		}
+		Ok(())
+	}
+
+	fn guild_channel(&self, guild: GuildId) -> Result<Option<ChannelId>, StorageError> {
+		let channel = self.conn.query_row(
+			"SELECT channel_id FROM guilds WHERE guild_id = ?1",
+			params![guild.0 as i64],
+			|row| id(row, 0),
+		).optional()?;
+		Ok(channel.map(ChannelId))
+	}
+
+	fn bind_guild(&mut self, guild: GuildId, channel: ChannelId) -> Result<Option<ChannelId>, StorageError> {
+		let old = self.guild_channel(guild)?;
+		self.conn.execute(
+			"INSERT INTO guilds (guild_id, channel_id) VALUES (?1, ?2)
+				ON CONFLICT (guild_id) DO UPDATE SET channel_id = excluded.channel_id",
+			params![guild.0 as i64, channel.0 as i64],
+		)?;
+		Ok(old)
+	}
+
+	fn guild_events(&self, guild: GuildId) -> Result<Option<EventFilter>, StorageError> {
+		let events = self.conn.query_row(
+			"SELECT muted_events FROM guilds WHERE guild_id = ?1",
+			params![guild.0 as i64],
+			|row| row.get(0).map(EventFilter::from_muted),
+		).optional()?;
+		Ok(events)
+	}
+
+	fn set_guild_events(&mut self, guild: GuildId, events: EventFilter) -> Result<(), StorageError> {
+		self.conn.execute(
+			"UPDATE guilds SET muted_events = ?1 WHERE guild_id = ?2",
+			params![events.muted(), guild.0 as i64],
+		)?;
+		Ok(())
+	}
+
+	fn add_track(&mut self, user: UserId, guild: GuildId) -> Result<(), StorageError> {
+		self.conn.execute(
+			"INSERT OR IGNORE INTO tracks (user_id, guild_id) VALUES (?1, ?2)",
+			params![user.0 as i64, guild.0 as i64],
+		)?;
+		Ok(())
+	}
+
+	fn remove_track(&mut self, user: UserId, guild: GuildId) -> Result<(), StorageError> {
+		self.conn.execute(
+			"DELETE FROM tracks WHERE user_id = ?1 AND guild_id = ?2",
+			params![user.0 as i64, guild.0 as i64],
+		)?;
+		Ok(())
+	}
+
+	fn guild_users(&self, guild: GuildId) -> Result<Vec<UserId>, StorageError> {
+		let mut stmt = self.conn.prepare("SELECT user_id FROM tracks WHERE guild_id = ?1")?;
+		let users = stmt.query_map(params![guild.0 as i64], |row| id(row, 0).map(UserId))?
+			.collect::<rusqlite::Result<_>>()?;
+		Ok(users)
+	}
+
+	fn track_channels(&self, user: UserId) -> Result<Vec<(ChannelId, EventFilter)>, StorageError> {
+		let mut stmt = self.conn.prepare(
+			"SELECT guilds.channel_id, guilds.muted_events FROM tracks JOIN guilds ON guilds.guild_id = tracks.guild_id WHERE tracks.user_id = ?1",
+		)?;
+		let channels = stmt.query_map(params![user.0 as i64], |row| Ok((ChannelId(id(row, 0)?), EventFilter::from_muted(row.get(1)?))))?
+			.collect::<rusqlite::Result<_>>()?;
+		Ok(channels)
+	}
+
+	fn record_match(&mut self, record: MatchRecord) -> Result<(), StorageError> {
+		insert_match(&self.conn, &record)?;
+		Ok(())
+	}
+
+	fn user_matches(&self, user: UserId, offset: usize, limit: usize) -> Result<Vec<MatchRecord>, StorageError> {
+		let mut stmt = self.conn.prepare(&format!(
+			"SELECT {} FROM matches WHERE user_id = ?1 ORDER BY ended_at DESC LIMIT ?2 OFFSET ?3",
+			MATCH_COLUMNS,
+		))?;
+		let matches = stmt.query_map(params![user.0 as i64, limit as i64, offset as i64], match_record)?
+			.collect::<rusqlite::Result<_>>()?;
+		Ok(matches)
+	}
+
+	fn user_matches_since(&self, user: UserId, since: i64) -> Result<Vec<MatchRecord>, StorageError> {
+		let mut stmt = self.conn.prepare(&format!(
+			"SELECT {} FROM matches WHERE user_id = ?1 AND ended_at >= ?2 ORDER BY ended_at DESC",
+			MATCH_COLUMNS,
+		))?;
+		let matches = stmt.query_map(params![user.0 as i64, since], match_record)?
+			.collect::<rusqlite::Result<_>>()?;
+		Ok(matches)
+	}
+
+	fn export(&self) -> Result<Snapshot, StorageError> {
+		let users = {
+			let mut stmt = self.conn.prepare("SELECT token, steam_id, user_id FROM users")?;
+			let rows = stmt.query_map([], |row| Ok((user_info(row)?, UserId(id(row, 2)?))))?;
+			rows.collect::<rusqlite::Result<_>>()?
+		};
+
+		let guilds = {
+			let mut stmt = self.conn.prepare("SELECT guild_id, channel_id, muted_events FROM guilds")?;
+			let rows = stmt.query_map([], |row| Ok((GuildId(id(row, 0)?), ChannelId(id(row, 1)?), EventFilter::from_muted(row.get(2)?))))?;
+			rows.collect::<rusqlite::Result<_>>()?
+		};
+
+		let tracks = {
+			let mut stmt = self.conn.prepare("SELECT user_id, guild_id FROM tracks")?;
+			let rows = stmt.query_map([], |row| Ok((UserId(id(row, 0)?), GuildId(id(row, 1)?))))?;
+			rows.collect::<rusqlite::Result<_>>()?
+		};
+
+		let matches = {
+			let mut stmt = self.conn.prepare(&format!("SELECT {} FROM matches", MATCH_COLUMNS))?;
+			let rows = stmt.query_map([], match_record)?;
+			rows.collect::<rusqlite::Result<_>>()?
+		};
+
+		let spectators = {
+			let mut stmt = self.conn.prepare("SELECT user_id, mode FROM spectators")?;
+			let rows = stmt.query_map([], |row| Ok((UserId(id(row, 0)?), spectate_mode(row, 1)?)))?;
+			rows.collect::<rusqlite::Result<_>>()?
+		};
+
+		Ok(Snapshot {
+			users,
+			guilds,
+			tracks,
+			matches,
+			spectators,
+		})
+	}
+
+	fn import(&mut self, snapshot: Snapshot) -> Result<(), StorageError> {
+		let tx = self.conn.transaction()?;
+
+		for (info, user) in &snapshot.users {
+			insert_user(&tx, *user, info)?;
+		}
+
+		for (guild, channel, events) in &snapshot.guilds {
+			tx.execute(
+				"INSERT OR REPLACE INTO guilds (guild_id, channel_id, muted_events) VALUES (?1, ?2, ?3)",
+				params![guild.0 as i64, channel.0 as i64, events.muted()],
+			)?;
+		}
+
+		for (user, guild) in &snapshot.tracks {
+			tx.execute(
+				"INSERT OR IGNORE INTO tracks (user_id, guild_id) VALUES (?1, ?2)",
+				params![user.0 as i64, guild.0 as i64],
+			)?;
+		}
+
+		for record in &snapshot.matches {
+			insert_match(&tx, record)?;
+		}
+
+		for (user, mode) in &snapshot.spectators {
+			tx.execute(
+				"INSERT OR REPLACE INTO spectators (user_id, mode) VALUES (?1, ?2)",
+				params![user.0 as i64, mode.name()],
+			)?;
+		}
+
+		tx.commit()?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn open() -> SqliteStorage {
+		let mut conn = Connection::open_in_memory().unwrap();
+		migrate(&mut conn).unwrap();
+		SqliteStorage { conn }
+	}
+
+	fn info(token: &str, steam_id: SteamId) -> UserInfo {
+		UserInfo { token: Ulid::from_str(token).unwrap(), steam_id }
+	}
+
+	fn record(match_id: u64, user: u64) -> MatchRecord {
+		MatchRecord {
+			match_id,
+			steam_id: 76561197960287930,
+			user: UserId(user),
+			hero: "npc_dota_hero_antimage".to_owned(),
+			kills: 10,
+			deaths: 2,
+			assists: 5,
+			last_hits: 300,
+			denies: 12,
+			gpm: 650,
+			xpm: 700,
+			net_worth: Some(25000),
+			duration: 2400,
+			team: "radiant".to_owned(),
+			won: Some(true),
+			ended_at: 1_660_000_000 + match_id as i64,
+		}
+	}
+
+	#[test]
+	fn migrates_to_latest_version() {
+		let storage = open();
+		let version: usize = storage.conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+		assert_eq!(version, MIGRATIONS.len());
+	}
+
+	#[test]
+	fn migrating_twice_is_a_no_op() {
+		let mut storage = open();
+		storage.bind_guild(GuildId(10), ChannelId(100)).unwrap();
+
+		migrate(&mut storage.conn).unwrap();
+		assert_eq!(storage.guild_channel(GuildId(10)).unwrap(), Some(ChannelId(100)));
+	}
+
+	#[test]
+	fn rejects_newer_schema() {
+		let mut conn = Connection::open_in_memory().unwrap();
+		conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1).unwrap();
+
+		match migrate(&mut conn) {
+			Err(StorageError::UnknownSchema(v)) => assert_eq!(v, MIGRATIONS.len() + 1),
+			other => panic!("expected UnknownSchema, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn registering_again_replaces_token() {
+		let mut storage = open();
+		let old = info("01ARZ3NDEKTSV4RRFFQ69G5FAV", 76561197960287930);
+		let new = info("01BX5ZZKBKACTAV9WEVGEMMVRZ", 76561197960287930);
+
+		storage.register_user(UserId(1), old).unwrap();
+		storage.register_user(UserId(1), new).unwrap();
+
+		assert_eq!(storage.find_user(&old).unwrap(), None);
+		assert_eq!(storage.find_user(&new).unwrap(), Some(UserId(1)));
+		assert_eq!(storage.tokens().unwrap(), [new.token].into_iter().collect());
+	}
+
+	#[test]
+	fn shared_steam_account_goes_to_lowest_user() {
+		let mut storage = open();
+		storage.register_user(UserId(3), info("01ARZ3NDEKTSV4RRFFQ69G5FAV", 76561197960287930)).unwrap();
+		storage.register_user(UserId(2), info("01BX5ZZKBKACTAV9WEVGEMMVRZ", 76561197960287930)).unwrap();
+
+		assert_eq!(storage.steam_user(76561197960287930).unwrap(), Some(UserId(2)));
+		assert_eq!(storage.steam_user(76561197960287931).unwrap(), None);
+	}
+
+	#[test]
+	fn round_trips_snapshot() {
+		let mut source = open();
+		source.register_user(UserId(1), info("01ARZ3NDEKTSV4RRFFQ69G5FAV", 76561197960287930)).unwrap();
+		source.register_user(UserId(2), info("01BX5ZZKBKACTAV9WEVGEMMVRZ", 76561197960287931)).unwrap();
+		source.bind_guild(GuildId(10), ChannelId(100)).unwrap();
+		source.set_guild_events(GuildId(10), EventFilter::from_muted(0b101)).unwrap();
+		source.add_track(UserId(1), GuildId(10)).unwrap();
+		source.record_match(record(1, 1)).unwrap();
+		source.record_match(record(2, 1)).unwrap();
+		source.set_spectate_mode(UserId(2), SpectateMode::Scoreboard).unwrap();
+
+		let mut target = open();
+		target.import(source.export().unwrap()).unwrap();
+
+		let (mut before, mut after) = (source.export().unwrap(), target.export().unwrap());
+		before.users.sort_by_key(|(_, user)| *user);
+		after.users.sort_by_key(|(_, user)| *user);
+		assert_eq!(after.users, before.users);
+		assert_eq!(after.guilds, before.guilds);
+		assert_eq!(after.tracks, before.tracks);
+		assert_eq!(after.spectators, before.spectators);
+		assert_eq!(target.user_matches(UserId(1), 0, 10).unwrap(), vec![record(2, 1), record(1, 1)]);
+	}
+
+	#[test]
+	fn import_replaces_existing_token() {
+		let mut storage = open();
+		let old = info("01ARZ3NDEKTSV4RRFFQ69G5FAV", 76561197960287930);
+		let new = info("01BX5ZZKBKACTAV9WEVGEMMVRZ", 76561197960287930);
+		storage.register_user(UserId(1), old).unwrap();
+
+		storage.import(Snapshot { users: vec![(new, UserId(1))], ..Default::default() }).unwrap();
+
+		assert_eq!(storage.find_user(&old).unwrap(), None);
+		assert_eq!(storage.find_user(&new).unwrap(), Some(UserId(1)));
+		assert_eq!(storage.tokens().unwrap(), [new.token].into_iter().collect());
+	}
+}